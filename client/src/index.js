@@ -154,14 +154,24 @@ class InputHandler {
     }
 }
 
-async function init() {
-    let resp = await fetch("/data.json")
+async function loadApp(m, canvas) {
+    // Prefer the compact binary data, it is much faster to download and parse
+    let resp = await fetch("/data.bin")
+    if (resp.ok) {
+        let data = new Uint8Array(await resp.arrayBuffer())
+        return m.App.from_binary(canvas, data)
+    }
+
+    resp = await fetch("/data.json")
     let data = await resp.json()
+    return new m.App(canvas, data)
+}
 
+async function init() {
     let m = await wasm;
     m.init();
 
-    let app = new m.App(document.getElementById('canvas'), data);
+    let app = await loadApp(m, document.getElementById('canvas'));
     let input_handler = new InputHandler(app)
     app.render()
 }
@@ -21,6 +21,22 @@ pub struct App {
 impl App {
     #[wasm_bindgen(constructor)]
     pub fn new(canvas: JsValue, data: JsValue) -> std::result::Result<App, JsValue> {
+        let to_js = |e: serde_wasm_bindgen::Error| JsValue::from_str(&e.to_string());
+        // Check the version first, stale data usually doesn't deserialize as the current Data
+        let version: common::DataVersion =
+            serde_wasm_bindgen::from_value(data.clone()).map_err(to_js)?;
+        version
+            .validate()
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+        let data = serde_wasm_bindgen::from_value(data).map_err(to_js)?;
+        Self::from_data(canvas, data)
+    }
+
+    fn from_data(canvas: JsValue, data: common::Data) -> std::result::Result<App, JsValue> {
+        data.validate()
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
         let canvas: web_sys::HtmlCanvasElement = canvas
             .dyn_into::<web_sys::HtmlCanvasElement>()
             .map_err(|_| ())
@@ -35,7 +51,6 @@ impl App {
 
         let gl = Arc::new(glow::Context::from_webgl2_context(webgl2_context));
 
-        let data = serde_wasm_bindgen::from_value(data).unwrap();
         let inner = path_planner::App::new(Arc::clone(&gl), data)
             .map_err(|e| JsValue::from_str(&format!("{:?}", e)))?;
 
@@ -46,6 +61,12 @@ impl App {
         })
     }
 
+    /// Construct the app from the compact binary format produced by the server (data.bin)
+    pub fn from_binary(canvas: JsValue, data: &[u8]) -> std::result::Result<App, JsValue> {
+        let data = common::binary::read(data).map_err(|e| JsValue::from_str(&e.to_string()))?;
+        Self::from_data(canvas, data)
+    }
+
     pub fn update_pointer_pos(&mut self, x: f32, y: f32) {
         let pixel_coord = PixelCoord { x, y };
 
@@ -102,7 +123,13 @@ impl App {
     }
 
     pub fn selected_tags(&self) -> JsValue {
-        serde_wasm_bindgen::to_value(&self.inner.selected_tags()).unwrap()
+        let tags: Vec<String> = self
+            .inner
+            .selected_tags()
+            .into_iter()
+            .map(|(key, value)| format!("{key}={value}"))
+            .collect();
+        serde_wasm_bindgen::to_value(&tags).unwrap()
     }
 
     pub fn update_highlight(&self, regex: String, color: &[f32]) {
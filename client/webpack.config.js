@@ -23,7 +23,8 @@ module.exports = {
     new CopyPlugin({
       patterns: [
         { from: "./src/index.css", to: "index.css" },
-        { from: "./src/data.json", to: "data.json" }
+        { from: "./src/data.json", to: "data.json" },
+        { from: "./src/data.bin", to: "data.bin", noErrorOnMissing: true }
       ]
     }),
     new WasmPackPlugin({
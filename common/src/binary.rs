@@ -0,0 +1,675 @@
+//! Compact binary encoding of [`Data`]
+//!
+//! All integers are LEB128 varints unless noted otherwise. Signed values are zigzag encoded
+//! first.
+//!
+//! ```text
+//! magic           8 bytes, MAGIC
+//! version         u32 little endian, VERSION
+//! data version    varint, Data::version
+//! metadata
+//!   source count  varint
+//!   sources       strings
+//!   generated at  varint
+//!   bounding box  signed varints, min lat, min long, max lat, max long
+//!   has citation  u8, 0 or 1
+//!   citation      string, only if has citation is 1
+//! string count    varint
+//!   string        varint length followed by utf8 bytes
+//! node count      varint
+//!   osm id        signed varint, delta from the previous node
+//!   lat, long     signed varints, delta from the previous node
+//! height mask     one bit per node, set if the node has a height
+//! heights         f32 little endian, one per set bit in the height mask
+//! way count       varint
+//!   osm id        signed varint, delta from the previous way
+//!   tag count     varint
+//!   tags          key, value pairs of varint indices into the string table
+//!   node count    varint
+//!   nodes         signed varints, delta from the previous node index in the way
+//! edge count      varint
+//!   way           signed varint, delta from the previous edge's way
+//!   from          signed varint, delta from the previous edge's to
+//!   to            signed varint, delta from this edge's from
+//!   shape count   varint
+//!   shape         signed varints, delta from the previous node, starting at from
+//!   length        f32 little endian
+//!   grade         f32 little endian
+//!   speed class   u8, SpeedClass discriminant
+//!   access        u8, Access bits
+//!   ascent        f32 little endian
+//!   descent       f32 little endian
+//!   max grade     f32 little endian
+//! ```
+
+use crate::{Access, BoundingBox, Data, Edge, Metadata, Node, SpeedClass, Tag, Way};
+use std::{
+    error::Error,
+    fmt,
+    io::{self, Read, Write},
+};
+
+pub const MAGIC: &[u8; 8] = b"PPDATA\r\n";
+pub const VERSION: u32 = 7;
+
+#[derive(Debug)]
+pub enum ReadError {
+    Io(io::Error),
+    InvalidMagic,
+    UnsupportedVersion(u32),
+    InvalidVarint,
+    InvalidBool(u8),
+    InvalidString(std::string::FromUtf8Error),
+    InvalidStringIndex(usize),
+    InvalidNodeIndex(i64),
+    InvalidWayIndex(i64),
+    InvalidSpeedClass(u8),
+    InvalidAccess(u8),
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use ReadError::*;
+        match self {
+            Io(_) => write!(f, "Failed to read data"),
+            InvalidMagic => write!(f, "Not a path planner data file"),
+            UnsupportedVersion(v) => {
+                write!(f, "Unsupported data version {v}, expected {VERSION}")
+            }
+            InvalidVarint => write!(f, "Invalid varint"),
+            InvalidBool(v) => write!(f, "Invalid boolean {v}"),
+            InvalidString(_) => write!(f, "Invalid string in string table"),
+            InvalidStringIndex(i) => write!(f, "String index {i} out of range"),
+            InvalidNodeIndex(i) => write!(f, "Node index {i} out of range"),
+            InvalidWayIndex(i) => write!(f, "Way index {i} out of range"),
+            InvalidSpeedClass(v) => write!(f, "Invalid speed class {v}"),
+            InvalidAccess(v) => write!(f, "Invalid access flags {v:#x}"),
+        }
+    }
+}
+
+impl Error for ReadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        use ReadError::*;
+        match self {
+            Io(e) => Some(e),
+            InvalidString(e) => Some(e),
+            InvalidMagic
+            | UnsupportedVersion(_)
+            | InvalidVarint
+            | InvalidBool(_)
+            | InvalidStringIndex(_)
+            | InvalidNodeIndex(_)
+            | InvalidWayIndex(_)
+            | InvalidSpeedClass(_)
+            | InvalidAccess(_) => None,
+        }
+    }
+}
+
+/// Returns true if the provided bytes start with the binary format's magic
+pub fn is_binary(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+fn zigzag(v: i64) -> u64 {
+    ((v << 1) ^ (v >> 63)) as u64
+}
+
+fn unzigzag(v: u64) -> i64 {
+    ((v >> 1) as i64) ^ -((v & 1) as i64)
+}
+
+fn write_varint<W: Write>(w: &mut W, mut v: u64) -> io::Result<()> {
+    let mut buf = [0u8; 10];
+    let mut len = 0;
+    loop {
+        let byte = (v & 0x7f) as u8;
+        v >>= 7;
+        if v == 0 {
+            buf[len] = byte;
+            len += 1;
+            break;
+        }
+        buf[len] = byte | 0x80;
+        len += 1;
+    }
+    w.write_all(&buf[..len])
+}
+
+fn write_signed_varint<W: Write>(w: &mut W, v: i64) -> io::Result<()> {
+    write_varint(w, zigzag(v))
+}
+
+fn write_usize<W: Write>(w: &mut W, v: usize) -> io::Result<()> {
+    write_varint(w, v as u64)
+}
+
+fn write_string<W: Write>(w: &mut W, s: &str) -> io::Result<()> {
+    write_usize(w, s.len())?;
+    w.write_all(s.as_bytes())
+}
+
+fn write_metadata<W: Write>(w: &mut W, metadata: &Metadata) -> io::Result<()> {
+    write_usize(w, metadata.source_files.len())?;
+    for source in &metadata.source_files {
+        write_string(w, source)?;
+    }
+
+    write_varint(w, metadata.generated_at)?;
+
+    let bbox = &metadata.bounding_box;
+    for v in [bbox.min_lat, bbox.min_long, bbox.max_lat, bbox.max_long] {
+        write_signed_varint(w, v as i64)?;
+    }
+
+    match &metadata.elevation_citation {
+        Some(citation) => {
+            w.write_all(&[1])?;
+            write_string(w, citation)
+        }
+        None => w.write_all(&[0]),
+    }
+}
+
+pub fn write<W: Write>(data: &Data, mut w: W) -> io::Result<()> {
+    w.write_all(MAGIC)?;
+    w.write_all(&VERSION.to_le_bytes())?;
+    write_varint(&mut w, data.version as u64)?;
+    write_metadata(&mut w, &data.metadata)?;
+
+    write_usize(&mut w, data.strings.len())?;
+    for s in &data.strings {
+        write_string(&mut w, s)?;
+    }
+
+    write_usize(&mut w, data.nodes.len())?;
+    let mut last = (0i64, 0i64, 0i64);
+    for node in &data.nodes {
+        let lat = node.lat as i64;
+        let long = node.long as i64;
+        write_signed_varint(&mut w, node.osm_id.wrapping_sub(last.0))?;
+        write_signed_varint(&mut w, lat - last.1)?;
+        write_signed_varint(&mut w, long - last.2)?;
+        last = (node.osm_id, lat, long);
+    }
+
+    let mut height_mask = vec![0u8; data.nodes.len().div_ceil(8)];
+    for (i, node) in data.nodes.iter().enumerate() {
+        if node.height.is_some() {
+            height_mask[i / 8] |= 1 << (i % 8);
+        }
+    }
+    w.write_all(&height_mask)?;
+
+    for height in data.nodes.iter().filter_map(|n| n.height) {
+        w.write_all(&height.to_le_bytes())?;
+    }
+
+    write_usize(&mut w, data.ways.len())?;
+    let mut last_id = 0i64;
+    for way in &data.ways {
+        write_signed_varint(&mut w, way.osm_id.wrapping_sub(last_id))?;
+        last_id = way.osm_id;
+
+        write_usize(&mut w, way.tags.len())?;
+        for tag in &way.tags {
+            write_varint(&mut w, tag.key as u64)?;
+            write_varint(&mut w, tag.value as u64)?;
+        }
+
+        write_usize(&mut w, way.nodes.len())?;
+        let mut last = 0i64;
+        for node in &way.nodes {
+            let node = *node as i64;
+            write_signed_varint(&mut w, node - last)?;
+            last = node;
+        }
+    }
+
+    write_usize(&mut w, data.edges.len())?;
+    let mut last = (0i64, 0i64);
+    for edge in &data.edges {
+        let (way, from, to) = (edge.way as i64, edge.from as i64, edge.to as i64);
+        write_signed_varint(&mut w, way - last.0)?;
+        write_signed_varint(&mut w, from - last.1)?;
+        write_signed_varint(&mut w, to - from)?;
+        last = (way, to);
+
+        write_usize(&mut w, edge.shape.len())?;
+        let mut last_node = from;
+        for node in &edge.shape {
+            let node = *node as i64;
+            write_signed_varint(&mut w, node - last_node)?;
+            last_node = node;
+        }
+
+        w.write_all(&edge.length.to_le_bytes())?;
+        w.write_all(&edge.grade.to_le_bytes())?;
+        w.write_all(&[edge.speed_class as u8, edge.access.to_bits()])?;
+
+        for v in [edge.ascent, edge.descent, edge.max_grade] {
+            w.write_all(&v.to_le_bytes())?;
+        }
+    }
+
+    w.flush()
+}
+
+struct Reader<R> {
+    inner: R,
+}
+
+impl<R: Read> Reader<R> {
+    fn bytes<const N: usize>(&mut self) -> Result<[u8; N], ReadError> {
+        let mut buf = [0; N];
+        self.inner.read_exact(&mut buf).map_err(ReadError::Io)?;
+        Ok(buf)
+    }
+
+    fn varint(&mut self) -> Result<u64, ReadError> {
+        let mut ret = 0u64;
+        for shift in (0..64).step_by(7) {
+            let [byte] = self.bytes::<1>()?;
+            ret |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(ret);
+            }
+        }
+
+        Err(ReadError::InvalidVarint)
+    }
+
+    fn signed_varint(&mut self) -> Result<i64, ReadError> {
+        Ok(unzigzag(self.varint()?))
+    }
+
+    fn usize(&mut self) -> Result<usize, ReadError> {
+        usize::try_from(self.varint()?).map_err(|_| ReadError::InvalidVarint)
+    }
+
+    fn i32(&mut self) -> Result<i32, ReadError> {
+        i32::try_from(self.signed_varint()?).map_err(|_| ReadError::InvalidVarint)
+    }
+
+    fn bool(&mut self) -> Result<bool, ReadError> {
+        match self.bytes::<1>()? {
+            [0] => Ok(false),
+            [1] => Ok(true),
+            [v] => Err(ReadError::InvalidBool(v)),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, ReadError> {
+        let len = self.usize()?;
+        let mut buf = Vec::new();
+        (&mut self.inner)
+            .take(len as u64)
+            .read_to_end(&mut buf)
+            .map_err(ReadError::Io)?;
+        if buf.len() != len {
+            return Err(ReadError::Io(io::ErrorKind::UnexpectedEof.into()));
+        }
+        String::from_utf8(buf).map_err(ReadError::InvalidString)
+    }
+
+    fn metadata(&mut self) -> Result<Metadata, ReadError> {
+        let num_sources = self.usize()?;
+        let source_files = (0..num_sources)
+            .map(|_| self.string())
+            .collect::<Result<_, _>>()?;
+
+        let generated_at = self.varint()?;
+
+        let bounding_box = BoundingBox {
+            min_lat: self.i32()?,
+            min_long: self.i32()?,
+            max_lat: self.i32()?,
+            max_long: self.i32()?,
+        };
+
+        let elevation_citation = if self.bool()? {
+            Some(self.string()?)
+        } else {
+            None
+        };
+
+        Ok(Metadata {
+            source_files,
+            generated_at,
+            bounding_box,
+            elevation_citation,
+        })
+    }
+}
+
+pub fn read<R: Read>(r: R) -> Result<Data, ReadError> {
+    let mut r = Reader { inner: r };
+
+    if &r.bytes::<8>()? != MAGIC {
+        return Err(ReadError::InvalidMagic);
+    }
+
+    let version = u32::from_le_bytes(r.bytes::<4>()?);
+    if version != VERSION {
+        return Err(ReadError::UnsupportedVersion(version));
+    }
+
+    let data_version = u32::try_from(r.varint()?).map_err(|_| ReadError::InvalidVarint)?;
+    let metadata = r.metadata()?;
+
+    // Counts come from the file, so don't trust them for preallocation beyond what a sane file
+    // would contain
+    const MAX_PREALLOC: usize = 1 << 20;
+
+    let num_strings = r.usize()?;
+    let mut strings = Vec::with_capacity(num_strings.min(MAX_PREALLOC));
+    for _ in 0..num_strings {
+        strings.push(r.string()?);
+    }
+
+    let num_nodes = r.usize()?;
+    let mut nodes = Vec::with_capacity(num_nodes.min(MAX_PREALLOC));
+    let mut last = (0i64, 0i64, 0i64);
+    for _ in 0..num_nodes {
+        let osm_id = last.0.wrapping_add(r.signed_varint()?);
+        let lat = last.1 + r.signed_varint()?;
+        let long = last.2 + r.signed_varint()?;
+        last = (osm_id, lat, long);
+        nodes.push(Node {
+            osm_id,
+            lat: lat as i32,
+            long: long as i32,
+            height: None,
+        });
+    }
+
+    let mut height_mask = vec![0u8; num_nodes.div_ceil(8)];
+    r.inner
+        .read_exact(&mut height_mask)
+        .map_err(ReadError::Io)?;
+
+    for (i, node) in nodes.iter_mut().enumerate() {
+        if height_mask[i / 8] & (1 << (i % 8)) != 0 {
+            node.height = Some(f32::from_le_bytes(r.bytes::<4>()?));
+        }
+    }
+
+    let num_ways = r.usize()?;
+    let mut ways = Vec::with_capacity(num_ways.min(MAX_PREALLOC));
+    let mut last_id = 0i64;
+    for _ in 0..num_ways {
+        let osm_id = last_id.wrapping_add(r.signed_varint()?);
+        last_id = osm_id;
+
+        let num_tags = r.usize()?;
+        let mut tags = Vec::with_capacity(num_tags.min(MAX_PREALLOC));
+        for _ in 0..num_tags {
+            let mut string_id = || {
+                let idx = r.usize()?;
+                if idx >= strings.len() {
+                    return Err(ReadError::InvalidStringIndex(idx));
+                }
+                Ok(idx as u32)
+            };
+
+            tags.push(Tag {
+                key: string_id()?,
+                value: string_id()?,
+            });
+        }
+
+        let num_way_nodes = r.usize()?;
+        let mut way_nodes = Vec::with_capacity(num_way_nodes.min(MAX_PREALLOC));
+        let mut last = 0i64;
+        for _ in 0..num_way_nodes {
+            let node = last + r.signed_varint()?;
+            if node < 0 || node as usize >= nodes.len() {
+                return Err(ReadError::InvalidNodeIndex(node));
+            }
+            way_nodes.push(node as usize);
+            last = node;
+        }
+
+        ways.push(Way {
+            osm_id,
+            tags,
+            nodes: way_nodes,
+        });
+    }
+
+    let node_index = |v: i64| match usize::try_from(v) {
+        Ok(i) if i < nodes.len() => Ok(i),
+        _ => Err(ReadError::InvalidNodeIndex(v)),
+    };
+
+    let num_edges = r.usize()?;
+    let mut edges = Vec::with_capacity(num_edges.min(MAX_PREALLOC));
+    let mut last = (0i64, 0i64);
+    for _ in 0..num_edges {
+        let way = last.0 + r.signed_varint()?;
+        let from = last.1 + r.signed_varint()?;
+        let to = from + r.signed_varint()?;
+        last = (way, to);
+
+        let shape_len = r.usize()?;
+        let mut shape = Vec::with_capacity(shape_len.min(MAX_PREALLOC));
+        let mut last_node = from;
+        for _ in 0..shape_len {
+            last_node += r.signed_varint()?;
+            shape.push(node_index(last_node)?);
+        }
+
+        let way_idx = match usize::try_from(way) {
+            Ok(i) if i < ways.len() => i,
+            _ => return Err(ReadError::InvalidWayIndex(way)),
+        };
+
+        let length = f32::from_le_bytes(r.bytes::<4>()?);
+        let grade = f32::from_le_bytes(r.bytes::<4>()?);
+        let [speed_class, access] = r.bytes::<2>()?;
+
+        edges.push(Edge {
+            way: way_idx,
+            from: node_index(from)?,
+            to: node_index(to)?,
+            shape,
+            length,
+            grade,
+            speed_class: SpeedClass::from_u8(speed_class)
+                .ok_or(ReadError::InvalidSpeedClass(speed_class))?,
+            access: Access::from_bits(access).ok_or(ReadError::InvalidAccess(access))?,
+            ascent: f32::from_le_bytes(r.bytes::<4>()?),
+            descent: f32::from_le_bytes(r.bytes::<4>()?),
+            max_grade: f32::from_le_bytes(r.bytes::<4>()?),
+        });
+    }
+
+    Ok(Data {
+        version: data_version,
+        metadata,
+        strings,
+        nodes,
+        ways,
+        edges,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(osm_id: i64, lat: i32, long: i32, height: Option<f32>) -> Node {
+        Node {
+            osm_id,
+            lat,
+            long,
+            height,
+        }
+    }
+
+    fn edge(way: usize, from: usize, to: usize, shape: &[usize]) -> Edge {
+        Edge {
+            way,
+            from,
+            to,
+            shape: shape.to_vec(),
+            length: 12.5,
+            grade: -0.03,
+            speed_class: SpeedClass::Local,
+            access: Access {
+                foot: true,
+                bicycle: false,
+                motor_vehicle: true,
+            },
+            ascent: 0.0,
+            descent: 1.25,
+            max_grade: 0.1,
+        }
+    }
+
+    fn sample() -> Data {
+        // Ids, positions and indices that go down as well as up so that deltas are negative
+        let nodes = vec![
+            node(100, 490_000_000, -1_230_000_000, Some(12.5)),
+            node(-5, -335_000_000, 1_510_000_000, None),
+            node(i64::MAX, i32::MAX, i32::MIN, Some(-3.0)),
+            node(i64::MIN, i32::MIN, i32::MAX, None),
+            node(7, 0, 0, Some(0.0)),
+            node(6, -1, 1, None),
+            node(5, 1, -1, None),
+            node(4, 2, -2, Some(f32::MAX)),
+            // Past the first byte of the height mask
+            node(3, 3, -3, None),
+            node(2, 4, -4, Some(-0.5)),
+        ];
+
+        Data {
+            version: crate::DATA_VERSION,
+            metadata: Metadata {
+                source_files: vec!["a.pbf".into(), "tiles/ü.asc".into()],
+                generated_at: 1_700_000_000,
+                bounding_box: BoundingBox {
+                    min_lat: i32::MIN,
+                    min_long: -1,
+                    max_lat: i32::MAX,
+                    max_long: 0,
+                },
+                elevation_citation: Some("Someone".into()),
+            },
+            strings: vec!["highway".into(), "path".into(), String::new()],
+            nodes,
+            ways: vec![
+                Way {
+                    osm_id: 50,
+                    tags: vec![Tag { key: 0, value: 1 }, Tag { key: 2, value: 2 }],
+                    nodes: vec![9, 0, 4, 8, 5],
+                },
+                Way {
+                    osm_id: -50,
+                    tags: Vec::new(),
+                    nodes: vec![3, 2],
+                },
+                Way {
+                    osm_id: 10,
+                    tags: vec![Tag { key: 0, value: 2 }],
+                    nodes: vec![1, 6, 7, 1],
+                },
+            ],
+            edges: vec![
+                edge(0, 9, 5, &[0, 4, 8]),
+                edge(2, 1, 7, &[6]),
+                edge(2, 7, 1, &[]),
+                edge(1, 3, 2, &[]),
+            ],
+        }
+    }
+
+    fn assert_same(a: &Data, b: &Data) {
+        let nodes = |d: &Data| -> Vec<_> {
+            d.nodes
+                .iter()
+                .map(|n| (n.osm_id, n.lat, n.long, n.height))
+                .collect()
+        };
+        let ways = |d: &Data| -> Vec<_> {
+            d.ways
+                .iter()
+                .map(|w| (w.osm_id, w.tags.clone(), w.nodes.clone()))
+                .collect()
+        };
+
+        assert_eq!(a.version, b.version);
+        assert_eq!(a.metadata, b.metadata);
+        assert_eq!(a.strings, b.strings);
+        assert_eq!(nodes(a), nodes(b));
+        assert_eq!(ways(a), ways(b));
+        assert_eq!(a.edges, b.edges);
+    }
+
+    fn round_trip(data: &Data) -> Data {
+        let mut buf = Vec::new();
+        write(data, &mut buf).unwrap();
+        assert!(is_binary(&buf));
+
+        let ret = read(&buf[..]).unwrap();
+
+        // Writing what we read gives back the same bytes
+        let mut again = Vec::new();
+        write(&ret, &mut again).unwrap();
+        assert_eq!(buf, again);
+
+        ret
+    }
+
+    #[test]
+    fn round_trip_sample() {
+        let data = sample();
+        assert_same(&round_trip(&data), &data);
+    }
+
+    #[test]
+    fn round_trip_empty() {
+        let data = Data {
+            version: crate::DATA_VERSION,
+            metadata: Metadata::default(),
+            strings: Vec::new(),
+            nodes: Vec::new(),
+            ways: Vec::new(),
+            edges: Vec::new(),
+        };
+        assert_same(&round_trip(&data), &data);
+    }
+
+    #[test]
+    fn zigzag_round_trip() {
+        for v in [0, 1, -1, 63, -64, 64, i32::MIN as i64, i64::MAX, i64::MIN] {
+            assert_eq!(unzigzag(zigzag(v)), v);
+        }
+        assert_eq!(zigzag(-1), 1);
+        assert_eq!(zigzag(1), 2);
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        let mut buf = Vec::new();
+        write(&sample(), &mut buf).unwrap();
+
+        assert!(matches!(
+            read(&b"not data"[..]),
+            Err(ReadError::InvalidMagic)
+        ));
+        for len in [0, 8, 12, buf.len() / 2, buf.len() - 1] {
+            assert!(read(&buf[..len]).is_err(), "truncated at {len} read fine");
+        }
+
+        let mut data = sample();
+        data.ways[0].nodes[1] = data.nodes.len();
+        let mut buf = Vec::new();
+        write(&data, &mut buf).unwrap();
+        assert!(matches!(
+            read(&buf[..]),
+            Err(ReadError::InvalidNodeIndex(10))
+        ));
+    }
+}
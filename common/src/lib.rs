@@ -1,20 +1,436 @@
 use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, error::Error, fmt};
+
+pub mod binary;
+
+/// Version of the [`Data`] schema. Bump whenever the meaning or layout of any field changes so
+/// that frontends reject data generated by an older daemon instead of misinterpreting it
+pub const DATA_VERSION: u32 = 6;
 
 #[derive(Serialize, Deserialize)]
 pub struct Node {
+    #[serde(default)]
+    pub osm_id: i64,
     pub lat: i32,
     pub long: i32,
     pub height: Option<f32>,
 }
 
+/// Mean radius of the earth
+const EARTH_RADIUS_M: f64 = 6_371_000.0;
+
+/// Great circle distance between two nodes in meters. Edge lengths and the path planner's
+/// heuristic both come from here, so the heuristic can't claim two nodes are further apart than
+/// the edges between them add up to
+pub fn distance_m(a: &Node, b: &Node) -> f32 {
+    let to_radians = |v: i64| (v as f64 / 10000000.0).to_radians();
+    let (lat_a, lat_b) = (to_radians(a.lat as i64), to_radians(b.lat as i64));
+    // Differences are taken before converting so that nearby nodes don't lose precision
+    let lat_dist = to_radians(b.lat as i64 - a.lat as i64);
+    let long_dist = to_radians(b.long as i64 - a.long as i64);
+
+    let h = (lat_dist / 2.0).sin().powi(2)
+        + lat_a.cos() * lat_b.cos() * (long_dist / 2.0).sin().powi(2);
+    (2.0 * EARTH_RADIUS_M * h.sqrt().min(1.0).asin()) as f32
+}
+
+/// An osm key/value pair. Both are indices into [`Data::strings`]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Tag {
+    pub key: u32,
+    pub value: u32,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct Way {
-    pub tags: Vec<String>,
+    #[serde(default)]
+    pub osm_id: i64,
+    pub tags: Vec<Tag>,
     pub nodes: Vec<usize>,
 }
 
+impl Way {
+    /// Link to the way on openstreetmap.org
+    pub fn osm_url(&self) -> String {
+        format!("https://www.openstreetmap.org/way/{}", self.osm_id)
+    }
+}
+
+/// How fast traffic on a way typically moves, derived from its highway tag. Ordered from fastest
+/// to slowest
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum SpeedClass {
+    Motorway = 0,
+    Arterial = 1,
+    Collector = 2,
+    Local = 3,
+    Service = 4,
+    Track = 5,
+    #[default]
+    Path = 6,
+}
+
+impl SpeedClass {
+    pub fn from_u8(v: u8) -> Option<SpeedClass> {
+        use SpeedClass::*;
+        let ret = match v {
+            0 => Motorway,
+            1 => Arterial,
+            2 => Collector,
+            3 => Local,
+            4 => Service,
+            5 => Track,
+            6 => Path,
+            _ => return None,
+        };
+        Some(ret)
+    }
+}
+
+/// Who may travel along an edge
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Access {
+    pub foot: bool,
+    pub bicycle: bool,
+    pub motor_vehicle: bool,
+}
+
+impl Access {
+    const FOOT: u8 = 1 << 0;
+    const BICYCLE: u8 = 1 << 1;
+    const MOTOR_VEHICLE: u8 = 1 << 2;
+
+    pub fn to_bits(self) -> u8 {
+        let mut ret = 0;
+        for (allowed, bit) in [
+            (self.foot, Access::FOOT),
+            (self.bicycle, Access::BICYCLE),
+            (self.motor_vehicle, Access::MOTOR_VEHICLE),
+        ] {
+            if allowed {
+                ret |= bit;
+            }
+        }
+        ret
+    }
+
+    /// None if bits outside of the known flags are set
+    pub fn from_bits(bits: u8) -> Option<Access> {
+        if bits & !(Access::FOOT | Access::BICYCLE | Access::MOTOR_VEHICLE) != 0 {
+            return None;
+        }
+
+        Some(Access {
+            foot: bits & Access::FOOT != 0,
+            bicycle: bits & Access::BICYCLE != 0,
+            motor_vehicle: bits & Access::MOTOR_VEHICLE != 0,
+        })
+    }
+}
+
+/// A routing edge along a single way, with everything a consumer needs to route over it without
+/// going back to the way's tags. Nodes that only shape the way rather than connect it to anything
+/// are folded into [`Edge::shape`], so `from` and `to` are junctions or way ends
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Edge {
+    /// Index into [`Data::ways`]
+    pub way: usize,
+    /// Indices into [`Data::nodes`]
+    pub from: usize,
+    pub to: usize,
+    /// Nodes between `from` and `to`, in order. Indices into [`Data::nodes`]
+    #[serde(default)]
+    pub shape: Vec<usize>,
+    /// Meters, following the shape
+    pub length: f32,
+    /// Net rise over run between `from` and `to`, negative when going downhill. 0 if either end has
+    /// no height
+    pub grade: f32,
+    pub speed_class: SpeedClass,
+    pub access: Access,
+    /// Meters climbed going from `from` to `to`, including any hills between the two nodes
+    pub ascent: f32,
+    /// Meters dropped going from `from` to `to`
+    pub descent: f32,
+    /// Steepest stretch of the edge as rise over run, in either direction
+    pub max_grade: f32,
+}
+
+/// Deduplicates strings into a table that [`Tag`]s can index into
+#[derive(Default)]
+pub struct StringInterner {
+    strings: Vec<String>,
+    ids: HashMap<String, u32>,
+}
+
+impl StringInterner {
+    pub fn intern(&mut self, s: &str) -> u32 {
+        if let Some(id) = self.ids.get(s) {
+            return *id;
+        }
+
+        let id = self.strings.len() as u32;
+        self.strings.push(s.to_string());
+        self.ids.insert(s.to_string(), id);
+        id
+    }
+
+    pub fn tag(&mut self, key: &str, value: &str) -> Tag {
+        Tag {
+            key: self.intern(key),
+            value: self.intern(value),
+        }
+    }
+
+    pub fn into_strings(self) -> Vec<String> {
+        self.strings
+    }
+}
+
+/// Bounds of all nodes, in decimicro degrees
+#[derive(Serialize, Deserialize, Default, Clone, Debug, PartialEq, Eq)]
+pub struct BoundingBox {
+    pub min_lat: i32,
+    pub min_long: i32,
+    pub max_lat: i32,
+    pub max_long: i32,
+}
+
+impl BoundingBox {
+    pub fn from_nodes(nodes: &[Node]) -> BoundingBox {
+        if nodes.is_empty() {
+            return BoundingBox::default();
+        }
+
+        let mut ret = BoundingBox {
+            min_lat: i32::MAX,
+            min_long: i32::MAX,
+            max_lat: i32::MIN,
+            max_long: i32::MIN,
+        };
+
+        for node in nodes {
+            ret.min_lat = ret.min_lat.min(node.lat);
+            ret.min_long = ret.min_long.min(node.long);
+            ret.max_lat = ret.max_lat.max(node.lat);
+            ret.max_long = ret.max_long.max(node.long);
+        }
+
+        ret
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, Debug, PartialEq)]
+pub struct Metadata {
+    /// Inputs the data was generated from
+    pub source_files: Vec<String>,
+    /// Seconds since the unix epoch
+    pub generated_at: u64,
+    pub bounding_box: BoundingBox,
+    /// Attribution required by the elevation data provider, if any
+    pub elevation_citation: Option<String>,
+}
+
+/// Just the version of serialized [`Data`]. Other fields change layout between versions, so stale
+/// data usually fails to deserialize as [`Data`]. Deserialize this first to tell the user why
+#[derive(Deserialize)]
+pub struct DataVersion {
+    // Data from before versioning has no version field
+    #[serde(default)]
+    pub version: u32,
+}
+
+impl DataVersion {
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        if self.version != DATA_VERSION {
+            return Err(ValidationError::UnsupportedVersion(self.version));
+        }
+
+        Ok(())
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct Data {
+    /// See [`DataVersion`]
+    pub version: u32,
+    #[serde(default)]
+    pub metadata: Metadata,
+    /// Interned tag keys and values
+    pub strings: Vec<String>,
     pub nodes: Vec<Node>,
     pub ways: Vec<Way>,
+    /// Routing graph over the ways, in way order
+    #[serde(default)]
+    pub edges: Vec<Edge>,
+}
+
+#[derive(Debug)]
+pub enum ValidationError {
+    UnsupportedVersion(u32),
+    NodeIndexOutOfRange { way: usize, node: usize },
+    StringIndexOutOfRange { way: usize, string: u32 },
+    TooFewNodes(usize),
+    EdgeOutOfRange(usize),
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use ValidationError::*;
+        match self {
+            UnsupportedVersion(v) => write!(
+                f,
+                "Data version {v} is not supported, expected {DATA_VERSION}. Regenerate it with a matching server"
+            ),
+            NodeIndexOutOfRange { way, node } => {
+                write!(f, "Way {way} references node {node}, which does not exist")
+            }
+            StringIndexOutOfRange { way, string } => {
+                write!(f, "Way {way} has a tag referencing string {string}, which does not exist")
+            }
+            TooFewNodes(way) => write!(f, "Way {way} has fewer than 2 nodes"),
+            EdgeOutOfRange(edge) => {
+                write!(f, "Edge {edge} references a node or way which does not exist")
+            }
+        }
+    }
+}
+
+impl Error for ValidationError {}
+
+impl Data {
+    /// Check that the data was generated for this version of the schema and that it is internally
+    /// consistent. Consumers index into nodes with way node ids without checking, so this should
+    /// be called before using data from an untrusted or possibly stale source
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        DataVersion {
+            version: self.version,
+        }
+        .validate()?;
+
+        for (i, way) in self.ways.iter().enumerate() {
+            if way.nodes.len() < 2 {
+                return Err(ValidationError::TooFewNodes(i));
+            }
+
+            if let Some(node) = way.nodes.iter().find(|n| **n >= self.nodes.len()) {
+                return Err(ValidationError::NodeIndexOutOfRange {
+                    way: i,
+                    node: *node,
+                });
+            }
+
+            let mut strings = way.tags.iter().flat_map(|t| [t.key, t.value]);
+            if let Some(string) = strings.find(|s| *s as usize >= self.strings.len()) {
+                return Err(ValidationError::StringIndexOutOfRange { way: i, string });
+            }
+        }
+
+        for (i, edge) in self.edges.iter().enumerate() {
+            let mut nodes = [edge.from, edge.to]
+                .into_iter()
+                .chain(edge.shape.iter().copied());
+            if edge.way >= self.ways.len() || nodes.any(|n| n >= self.nodes.len()) {
+                return Err(ValidationError::EdgeOutOfRange(i));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Connected component of each node, following ways. Components are numbered from 0 in order
+    /// of their lowest node index. Nodes that aren't on any way are a component of their own
+    pub fn components(&self) -> Vec<u32> {
+        let mut parents: Vec<usize> = (0..self.nodes.len()).collect();
+
+        fn root(parents: &mut [usize], mut node: usize) -> usize {
+            while parents[node] != node {
+                parents[node] = parents[parents[node]];
+                node = parents[node];
+            }
+            node
+        }
+
+        for way in &self.ways {
+            for pair in way.nodes.windows(2) {
+                let (a, b) = (root(&mut parents, pair[0]), root(&mut parents, pair[1]));
+                // Keep the lowest index as the root so numbering follows node order
+                parents[a.max(b)] = a.min(b);
+            }
+        }
+
+        let mut ids = vec![u32::MAX; self.nodes.len()];
+        let mut next_id = 0;
+        (0..self.nodes.len())
+            .map(|node| {
+                let root = root(&mut parents, node);
+                if ids[root] == u32::MAX {
+                    ids[root] = next_id;
+                    next_id += 1;
+                }
+                ids[root]
+            })
+            .collect()
+    }
+
+    pub fn string(&self, id: u32) -> &str {
+        &self.strings[id as usize]
+    }
+
+    /// The way's tags as (key, value)
+    pub fn tags<'a>(&'a self, way: &'a Way) -> impl Iterator<Item = (&'a str, &'a str)> + 'a {
+        way.tags
+            .iter()
+            .map(|t| (self.string(t.key), self.string(t.value)))
+    }
+
+    pub fn tag_value<'a>(&'a self, way: &'a Way, key: &str) -> Option<&'a str> {
+        self.tags(way).find(|(k, _)| *k == key).map(|(_, v)| v)
+    }
+
+    pub fn has_tag(&self, way: &Way, key: &str, value: &str) -> bool {
+        self.tag_value(way, key) == Some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stale_json_reports_version() {
+        // Version 1 stored tags as flat strings and had no string table
+        let v1 = r#"{
+            "version": 1,
+            "nodes": [{"lat": 0, "long": 0, "height": null}, {"lat": 1, "long": 1, "height": null}],
+            "ways": [{"tags": ["highway", "residential"], "nodes": [0, 1]}]
+        }"#;
+        // Data from before versioning had no version field either
+        let unversioned = r#"{
+            "nodes": [{"lat": 0, "long": 0, "height": null}, {"lat": 1, "long": 1, "height": null}],
+            "ways": [{"tags": ["highway", "residential"], "nodes": [0, 1]}]
+        }"#;
+
+        for (json, expected) in [(v1, 1), (unversioned, 0)] {
+            assert!(serde_json::from_str::<Data>(json).is_err());
+
+            let version: DataVersion = serde_json::from_str(json).unwrap();
+            match version.validate() {
+                Err(ValidationError::UnsupportedVersion(v)) => assert_eq!(v, expected),
+                other => panic!("Expected an unsupported version, got {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn current_json_passes_version_check() {
+        let json =
+            format!(r#"{{"version": {DATA_VERSION}, "strings": [], "nodes": [], "ways": []}}"#);
+        let version: DataVersion = serde_json::from_str(&json).unwrap();
+        assert!(version.validate().is_ok());
+
+        let data: Data = serde_json::from_str(&json).unwrap();
+        assert!(data.validate().is_ok());
+    }
 }
@@ -1,13 +1,20 @@
-use common::{Data, Node, Way};
-use elevation_data::ElevationData;
-use osmpbf::Element;
-use std::collections::{HashMap, HashSet};
+use common::{Data, DataVersion};
+use ingest::{watch::InputWatcher, Interpolation, OsmData, OsmInput, Smoothing};
 use std::env;
 use std::fs::File;
-use std::io::{BufReader, BufWriter};
-use std::{borrow::Cow, error::Error as StdError, fmt, fs::OpenOptions, path::PathBuf};
-
-mod elevation_data;
+use std::io::{self, Write};
+use std::io::{BufRead, BufReader, BufWriter, Read};
+use std::time::Duration;
+use std::{
+    borrow::Cow,
+    error::Error as StdError,
+    fmt,
+    path::{Path, PathBuf},
+};
+use tempfile::NamedTempFile;
+
+mod report;
+mod stats;
 
 pub struct Error {
     reason: Cow<'static, str>,
@@ -39,7 +46,7 @@ impl fmt::Debug for Error {
         write!(f, "\n\nCaused by:\n{it}")?;
 
         while let Some(e) = it.source() {
-            fmt::Display::fmt(e, f)?;
+            write!(f, "\n{e}")?;
             it = e;
         }
         Ok(())
@@ -58,86 +65,13 @@ impl StdError for Error {
     }
 }
 
-pub fn data_from_osm_pbf<R>(pbf: R, elevation_data: &ElevationData) -> Result<Data, Error>
-where
-    R: std::io::Read + Send,
-{
-    let pbf_reader = osmpbf::ElementReader::new(pbf);
-
-    let mut nodes = HashMap::new();
-    let mut relevant_nodes = HashSet::new();
-    let mut ways = Vec::new();
-    pbf_reader
-        .for_each(|elem| match elem {
-            Element::Node(node) => {
-                let lat = node.decimicro_lat();
-                let long = node.decimicro_lon();
-                let height = elevation_data
-                    .height_at_lat_long(lat as f32 / 10000000.0, long as f32 / 10000000.0);
-                nodes.insert(node.id(), Node { lat, long, height });
-            }
-            Element::DenseNode(node) => {
-                let lat = node.decimicro_lat();
-                let long = node.decimicro_lon();
-                let height = elevation_data
-                    .height_at_lat_long(lat as f32 / 10000000.0, long as f32 / 10000000.0);
-                nodes.insert(node.id(), Node { lat, long, height });
-            }
-            Element::Way(way) => {
-                let mut tags = Vec::new();
-                let mut node_ids = Vec::new();
-                let tag_keys = way.tags().map(|(k, _)| k).collect::<Vec<_>>();
-                if !tag_keys.contains(&"highway") {
-                    return;
-                }
-                for (key, value) in way.tags() {
-                    if node_ids.is_empty() {
-                        for id in way.refs() {
-                            node_ids.push(id);
-                        }
-
-                        relevant_nodes.extend(node_ids.clone());
-                    }
-
-                    tags.push(format!("{key}/{value}"));
-                }
-                ways.push((node_ids, tags));
-            }
-            Element::Relation(_relation) => {}
-        })
-        .map_err(|e| Error::new("Failed to read osm pbf", e))?;
-
-    // Once we've walked the whole pbf, we can discard any nodes that are not related to our
-    // paths. Since this will end up being a subset of all ids, we also heal the way references
-    // to be indexes into a linear array of nodes. This has the nice side effect of simplifying
-    // some rendering code. We can just upload this array to the GPU in a vertex buffer and use
-    // the healed node ids as our index buffer
-    let (node_mapping, nodes): (HashMap<i64, usize>, Vec<Node>) = nodes
-        .into_iter()
-        .filter(|(k, _)| relevant_nodes.contains(k))
-        .enumerate()
-        .map(|(i, (k, v))| ((k, i), v))
-        .unzip();
-
-    let mut new_ways = Vec::new();
-    for way in ways.into_iter() {
-        new_ways.push(Way {
-            nodes: way.0.iter().map(|id| node_mapping[id]).collect(),
-            tags: way.1,
-        });
-    }
-
-    Ok(Data {
-        nodes,
-        ways: new_ways,
-    })
-}
-
 #[derive(Debug)]
 enum ArgParseError {
     InvalidArgument(String),
     MissingArgument(&'static str),
     MissingValue(&'static str),
+    InvalidValue(&'static str, String),
+    ConflictingArguments(&'static str, &'static str),
 }
 
 impl fmt::Display for ArgParseError {
@@ -147,6 +81,8 @@ impl fmt::Display for ArgParseError {
             InvalidArgument(s) => write!(f, "Invalid argument: {s}")?,
             MissingArgument(s) => write!(f, "Missing argument: {s}")?,
             MissingValue(s) => write!(f, "Missing value for {s}")?,
+            InvalidValue(s, v) => write!(f, "Invalid value for {s}: {v}")?,
+            ConflictingArguments(a, b) => write!(f, "{a} cannot be used with {b}")?,
         };
 
         write!(f, "\n\n{}", Args::help())
@@ -155,19 +91,60 @@ impl fmt::Display for ArgParseError {
 
 impl StdError for ArgParseError {}
 
+enum Command {
+    /// Build and publish the data
+    Generate,
+    /// Build the data and write a report of likely mistakes in the osm input instead
+    Report,
+}
+
 struct Args {
-    pbf_path: PathBuf,
-    elevation_path: PathBuf,
+    command: Command,
+    osm_input: OsmInput,
+    change_paths: Vec<PathBuf>,
+    elevation_paths: Vec<PathBuf>,
+    interpolation: Interpolation,
+    smoothing: Smoothing,
+    min_island_size: usize,
     www_path: PathBuf,
+    two_pass: bool,
+    interval: Option<Duration>,
+    watch: bool,
 }
 
 impl Args {
     const ELEVATION_LONG_ARG: &str = "--elevation-path";
     const ELEVATION_SHORT_ARG: &str = "-e";
+    const INTERPOLATION_ARG: &str = "--elevation-interpolation";
+    const SMOOTHING_ARG: &str = "--height-smoothing";
+    const MIN_ISLAND_SIZE_ARG: &str = "--min-island-size";
     const WWW_LONG_ARG: &str = "--www-path";
     const WWW_SHORT_ARG: &str = "-w";
     const OSM_LONG_ARG: &str = "--osm-pbf-path";
     const OSM_SHORT_ARG: &str = "-p";
+    const OSM_XML_LONG_ARG: &str = "--osm-xml-path";
+    const OSM_XML_SHORT_ARG: &str = "-x";
+    const OSM_CHANGE_LONG_ARG: &str = "--osm-change-path";
+    const OSM_CHANGE_SHORT_ARG: &str = "-c";
+    const TWO_PASS_ARG: &str = "--two-pass";
+    const INTERVAL_LONG_ARG: &str = "--interval";
+    const INTERVAL_SHORT_ARG: &str = "-i";
+    const WATCH_ARG: &str = "--watch";
+    const REPORT_COMMAND: &str = "report";
+    const STATS_COMMAND: &str = "stats";
+
+    /// The data file to summarize, if invoked as the stats command. That takes nothing but a path,
+    /// so it skips the usual argument parsing
+    fn stats_path<U: AsRef<str>>(inputs: &[U]) -> Result<Option<PathBuf>, ArgParseError> {
+        match inputs {
+            [_, command, rest @ ..] if command.as_ref() == Self::STATS_COMMAND => match rest {
+                [path] => Ok(Some(path.as_ref().into())),
+                [] => Err(ArgParseError::MissingValue(Self::STATS_COMMAND)),
+                [_, extra, ..] => Err(ArgParseError::InvalidArgument(extra.as_ref().into())),
+            },
+            _ => Ok(None),
+        }
+    }
 
     fn new<T, U>(inputs: T) -> Result<Args, ArgParseError>
     where
@@ -175,14 +152,30 @@ impl Args {
         U: AsRef<str>,
     {
         use ArgParseError as E;
-        let mut it = inputs.into_iter();
+        let mut it = inputs.into_iter().peekable();
         // Skip exe name
         it.next();
 
+        let command = match it.peek() {
+            Some(v) if v.as_ref() == Self::REPORT_COMMAND => {
+                it.next();
+                Command::Report
+            }
+            _ => Command::Generate,
+        };
+
         enum ArgData {
             Www(PathBuf),
             Osm(PathBuf),
+            OsmXml(PathBuf),
+            OsmChange(PathBuf),
             Elevation(PathBuf),
+            Interpolation(Interpolation),
+            Smoothing(Smoothing),
+            MinIslandSize(usize),
+            TwoPass,
+            Interval(Duration),
+            Watch,
             Help,
             None,
         }
@@ -213,12 +206,71 @@ impl Args {
                             .ok_or(ArgParseError::MissingValue(Args::OSM_LONG_ARG))?;
                         Ok(ArgData::Osm(val.as_ref().into()))
                     }
+                    Args::OSM_XML_LONG_ARG | Args::OSM_XML_SHORT_ARG => {
+                        let val = it
+                            .next()
+                            .ok_or(ArgParseError::MissingValue(Args::OSM_XML_LONG_ARG))?;
+                        Ok(ArgData::OsmXml(val.as_ref().into()))
+                    }
+                    Args::OSM_CHANGE_LONG_ARG | Args::OSM_CHANGE_SHORT_ARG => {
+                        let val = it
+                            .next()
+                            .ok_or(ArgParseError::MissingValue(Args::OSM_CHANGE_LONG_ARG))?;
+                        Ok(ArgData::OsmChange(val.as_ref().into()))
+                    }
                     Args::WWW_LONG_ARG | Args::WWW_SHORT_ARG => {
                         let val = it
                             .next()
                             .ok_or(ArgParseError::MissingValue(Args::WWW_LONG_ARG))?;
                         Ok(ArgData::Www(val.as_ref().into()))
                     }
+                    Args::INTERPOLATION_ARG => {
+                        let val = it
+                            .next()
+                            .ok_or(ArgParseError::MissingValue(Args::INTERPOLATION_ARG))?;
+                        let val = val.as_ref();
+                        let interpolation = val.parse().map_err(|_| {
+                            ArgParseError::InvalidValue(Args::INTERPOLATION_ARG, val.into())
+                        })?;
+                        Ok(ArgData::Interpolation(interpolation))
+                    }
+                    Args::SMOOTHING_ARG => {
+                        let val = it
+                            .next()
+                            .ok_or(ArgParseError::MissingValue(Args::SMOOTHING_ARG))?;
+                        let val = val.as_ref();
+                        let smoothing = val.parse().map_err(|_| {
+                            ArgParseError::InvalidValue(Args::SMOOTHING_ARG, val.into())
+                        })?;
+                        Ok(ArgData::Smoothing(smoothing))
+                    }
+                    Args::MIN_ISLAND_SIZE_ARG => {
+                        let val = it
+                            .next()
+                            .ok_or(ArgParseError::MissingValue(Args::MIN_ISLAND_SIZE_ARG))?;
+                        let val = val.as_ref();
+                        let size = val.parse().map_err(|_| {
+                            ArgParseError::InvalidValue(Args::MIN_ISLAND_SIZE_ARG, val.into())
+                        })?;
+                        Ok(ArgData::MinIslandSize(size))
+                    }
+                    Args::TWO_PASS_ARG => Ok(ArgData::TwoPass),
+                    Args::INTERVAL_LONG_ARG | Args::INTERVAL_SHORT_ARG => {
+                        let val = it
+                            .next()
+                            .ok_or(ArgParseError::MissingValue(Args::INTERVAL_LONG_ARG))?;
+                        let val = val.as_ref();
+                        // 0 would have us re-check the inputs in a busy loop
+                        let secs = val
+                            .parse::<u64>()
+                            .ok()
+                            .filter(|secs| *secs > 0)
+                            .ok_or_else(|| {
+                                ArgParseError::InvalidValue(Args::INTERVAL_LONG_ARG, val.into())
+                            })?;
+                        Ok(ArgData::Interval(Duration::from_secs(secs)))
+                    }
+                    Args::WATCH_ARG => Ok(ArgData::Watch),
                     "--help" => Ok(ArgData::Help),
                     a => Err(ArgParseError::InvalidArgument(a.into())),
                 }
@@ -227,12 +279,28 @@ impl Args {
 
         let mut www_path = None;
         let mut pbf_path = None;
-        let mut elevation_path = None;
+        let mut xml_path = None;
+        let mut change_paths = Vec::new();
+        let mut elevation_paths = Vec::new();
+        let mut interpolation = Interpolation::default();
+        let mut smoothing = Smoothing::default();
+        let mut min_island_size = 0;
+        let mut two_pass = false;
+        let mut interval = None;
+        let mut watch = false;
         loop {
             match ArgData::try_from(&mut it)? {
                 ArgData::Osm(p) => pbf_path = Some(p),
-                ArgData::Elevation(p) => elevation_path = Some(p),
+                ArgData::OsmXml(p) => xml_path = Some(p),
+                ArgData::OsmChange(p) => change_paths.push(p),
+                ArgData::Elevation(p) => elevation_paths.push(p),
+                ArgData::Interpolation(i) => interpolation = i,
+                ArgData::Smoothing(s) => smoothing = s,
+                ArgData::MinIslandSize(s) => min_island_size = s,
                 ArgData::Www(p) => www_path = Some(p),
+                ArgData::TwoPass => two_pass = true,
+                ArgData::Interval(i) => interval = Some(i),
+                ArgData::Watch => watch = true,
                 ArgData::Help => {
                     eprintln!("{}", Args::help());
                     std::process::exit(0);
@@ -251,16 +319,86 @@ impl Args {
         }
 
         let www_path = unwrap_arg!(www_path, Self::WWW_LONG_ARG);
-        let pbf_path = unwrap_arg!(pbf_path, Self::OSM_LONG_ARG);
-        let elevation_path = unwrap_arg!(elevation_path, Self::ELEVATION_LONG_ARG);
+        if elevation_paths.is_empty() {
+            return Err(E::MissingArgument(Self::ELEVATION_LONG_ARG));
+        }
+
+        let osm_input = match (pbf_path, xml_path) {
+            (Some(p), None) => OsmInput::Pbf(p),
+            (None, Some(p)) => OsmInput::Xml(p),
+            (None, None) => return Err(E::MissingArgument(Self::OSM_LONG_ARG)),
+            (Some(_), Some(_)) => {
+                return Err(E::ConflictingArguments(
+                    Self::OSM_LONG_ARG,
+                    Self::OSM_XML_LONG_ARG,
+                ))
+            }
+        };
+
+        if two_pass {
+            if let OsmInput::Xml(_) = osm_input {
+                return Err(E::ConflictingArguments(
+                    Self::TWO_PASS_ARG,
+                    Self::OSM_XML_LONG_ARG,
+                ));
+            }
+
+            if !change_paths.is_empty() {
+                return Err(E::ConflictingArguments(
+                    Self::TWO_PASS_ARG,
+                    Self::OSM_CHANGE_LONG_ARG,
+                ));
+            }
+        }
+
+        if let Command::Report = command {
+            if watch {
+                return Err(E::ConflictingArguments(
+                    Self::REPORT_COMMAND,
+                    Self::WATCH_ARG,
+                ));
+            }
+
+            if interval.is_some() {
+                return Err(E::ConflictingArguments(
+                    Self::REPORT_COMMAND,
+                    Self::INTERVAL_LONG_ARG,
+                ));
+            }
+        }
 
         Ok(Args {
+            command,
             www_path,
-            pbf_path,
-            elevation_path,
+            osm_input,
+            change_paths,
+            elevation_paths,
+            interpolation,
+            smoothing,
+            min_island_size,
+            two_pass,
+            interval,
+            watch,
         })
     }
 
+    fn builder(&self) -> ingest::Builder {
+        let builder = self
+            .elevation_paths
+            .iter()
+            .fold(ingest::Builder::new(self.osm_input.clone()), |b, p| {
+                b.elevation_path(p)
+            });
+
+        self.change_paths
+            .iter()
+            .fold(builder, |b, p| b.change_path(p))
+            .interpolation(self.interpolation)
+            .smoothing(self.smoothing)
+            .min_island_size(self.min_island_size)
+            .two_pass(self.two_pass)
+    }
+
     fn help() -> String {
         let exe_name = match env::current_exe() {
             Ok(v) => v,
@@ -273,47 +411,210 @@ impl Args {
                   \n\
                   Periodically produce an updated data.json for path-planner \n\
                   \n\
+                  Run as {exe_name} {report} <ARGS> to write report.txt and report.geojson to WWW_PATH instead, \
+                  listing likely mistakes in the osm data such as missing junctions and suspicious tags \n\
+                  Run as {exe_name} {stats} <DATA_PATH> to summarize a previously generated data.json or data.bin \n\
+                  \n\
                   Args: \n\
                   \n\
-                  {www_long} | {www_short} <WWW_PATH>: Where to write the output\n\
-                  {elevation_long} | {elevation_short} <ELEVATION_PATH>: Where to read elevation data from\n\
+                  {www_long} | {www_short} <WWW_PATH>: Where to write the output (data.json and the compact data.bin)\n\
+                  {elevation_long} | {elevation_short} <ELEVATION_PATH>: Elevation raster (.asc, .asc.gz, .hgt or .tif), or directory of rasters, to read elevation data from. \
+                  May be given multiple times, where tiles overlap the finest resolution one is used\n\
+                  {interpolation} <nearest|bilinear|bicubic>: How to sample elevation between grid points. \
+                  Defaults to nearest\n\
+                  {smoothing} <none|average[:WINDOW]|kalman[:DEM_NOISE[:ROAD_NOISE]]>: How to smooth heights along ways. \
+                  average takes a window in meters (default 50), kalman the standard deviation of the DEM's error (default 3) \
+                  and of the change in height of a road over 100m (default 2). Defaults to none\n\
+                  {min_island_size} <NODES>: Drop groups of ways with fewer than this many nodes that connect to nothing else. \
+                  Defaults to 0, keeping everything\n\
                   {pbf_long} | {pbf_short} <PBF_PATH>: Where to read pbf data from\n\
+                  {xml_long} | {xml_short} <OSM_PATH>: Where to read osm xml data from, instead of a pbf. \
+                  May be .gz or .bz2 compressed\n\
+                  {change_long} | {change_short} <OSC_PATH>: osmChange file to apply on top of the osm input. \
+                  May be given multiple times, changes are applied in order\n\
+                  {two_pass}: Read the pbf twice, only keeping nodes referenced by highways. \
+                  Slower, but uses far less memory on large extracts\n\
+                  {interval_long} | {interval_short} <SECONDS>: Keep running, checking the inputs for changes at this interval. Must be at least 1\n\
+                  {watch}: Keep running, regenerating whenever the filesystem reports a change to the inputs\n\
+                  \n\
+                  Without {interval_long} or {watch}, data.json is generated once\n\
                   "
         , exe_name=exe_name.display()
         , www_long=Self::WWW_LONG_ARG
         , www_short=Self::WWW_SHORT_ARG
         , elevation_long=Self::ELEVATION_LONG_ARG
         , elevation_short=Self::ELEVATION_SHORT_ARG
+        , interpolation=Self::INTERPOLATION_ARG
+        , smoothing=Self::SMOOTHING_ARG
+        , min_island_size=Self::MIN_ISLAND_SIZE_ARG
         , pbf_long=Self::OSM_LONG_ARG
-        , pbf_short=Self::OSM_SHORT_ARG)
+        , pbf_short=Self::OSM_SHORT_ARG
+        , xml_long=Self::OSM_XML_LONG_ARG
+        , xml_short=Self::OSM_XML_SHORT_ARG
+        , change_long=Self::OSM_CHANGE_LONG_ARG
+        , change_short=Self::OSM_CHANGE_SHORT_ARG
+        , two_pass=Self::TWO_PASS_ARG
+        , interval_long=Self::INTERVAL_LONG_ARG
+        , interval_short=Self::INTERVAL_SHORT_ARG
+        , watch=Self::WATCH_ARG
+        , report=Self::REPORT_COMMAND
+        , stats=Self::STATS_COMMAND)
     }
 }
 
-fn main() -> Result<(), Error> {
-    let args =
-        Args::new(std::env::args()).map_err(|e| Error::new("Failed to parse arguments", e))?;
+/// Write a file to a temporary location in `www_path`, ready to be renamed into place with
+/// [`persist_file`]
+fn stage_file<F>(www_path: &Path, write: F) -> Result<NamedTempFile, Error>
+where
+    F: FnOnce(&mut BufWriter<&File>) -> Result<(), Error>,
+{
+    let f = NamedTempFile::new_in(www_path).map_err(|e| {
+        Error::new(
+            format!("Failed to create temp file in {}", www_path.display()),
+            e,
+        )
+    })?;
+
+    let mut writer = BufWriter::new(f.as_file());
+    write(&mut writer)?;
+    writer
+        .flush()
+        .map_err(|e| Error::new("Failed to write data", e))?;
+    drop(writer);
+
+    // Temp files are only readable by us, but our outputs are meant to be served
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        f.as_file()
+            .set_permissions(std::fs::Permissions::from_mode(0o644))
+            .map_err(|e| Error::new("Failed to set data permissions", e))?;
+    }
 
-    let elevation_file = File::open(args.elevation_path)
-        .map_err(|e| Error::new("Failed to open elevation file", e))?;
-    let elevation_data = elevation_data::parse_elevation_data(BufReader::new(elevation_file))
-        .map_err(|e| Error::new("Failed to parse elevation data", e))?;
+    Ok(f)
+}
+
+fn persist_file(f: NamedTempFile, www_path: &Path, name: &str) -> Result<(), Error> {
+    let output_path = www_path.join(name);
+    f.persist(&output_path)
+        .map_err(|e| Error::new(format!("Failed to write {}", output_path.display()), e))?;
+
+    Ok(())
+}
 
-    let pbf_file =
-        File::open(args.pbf_path).map_err(|e| Error::new("Failed to open pbf file", e))?;
-    let data = data_from_osm_pbf(BufReader::new(pbf_file), &elevation_data)
-        .map_err(|e| Error::new("Failed to retrieve data", e))?;
+/// Write a file next to its destination and rename it into place, so that anything serving or
+/// reading the old file never sees a partial write
+fn publish_file<F>(www_path: &Path, name: &str, write: F) -> Result<(), Error>
+where
+    F: FnOnce(&mut BufWriter<&File>) -> Result<(), Error>,
+{
+    persist_file(stage_file(www_path, write)?, www_path, name)
+}
 
-    let output_path = args.www_path.join("data.json");
-    let f = OpenOptions::new()
-        .write(true)
-        .create(true)
-        .truncate(true)
-        .open(&output_path)
-        .map_err(|e| Error::new(format!("Failed to open to {}", output_path.display()), e))?;
+fn publish(data: &Data, www_path: &Path) -> Result<(), Error> {
+    // Both files are written out before either is replaced, so a failure part way through
+    // leaves the previous pair in place rather than a json and bin from different runs
+    let json = stage_file(www_path, |w| {
+        serde_json::to_writer(w, data).map_err(|e| Error::new("Failed to serialize data", e))
+    })?;
+    let bin = stage_file(www_path, |w| {
+        common::binary::write(data, w).map_err(|e| Error::new("Failed to serialize data", e))
+    })?;
+
+    persist_file(json, www_path, "data.json")?;
+    persist_file(bin, www_path, "data.bin")
+}
 
-    let f = BufWriter::new(f);
+/// Read all inputs and build the network from them, everything short of publishing it
+fn generate(args: &Args) -> Result<OsmData, Error> {
+    args.builder()
+        .build()
+        .map_err(|e| Error::new("Failed to generate data", e))
+}
 
-    serde_json::to_writer(f, &data).map_err(|e| Error::new("Failed to serialize data", e))?;
+fn regenerate(args: &Args) -> Result<(), Error> {
+    let osm_data = generate(args)?;
+    publish(&osm_data.data, &args.www_path)
+}
 
+fn write_report(args: &Args) -> Result<(), Error> {
+    let osm_data = generate(args)?;
+    let issues = report::analyze(&osm_data);
+
+    publish_file(&args.www_path, "report.txt", |w| {
+        report::write_text(&osm_data.data, &issues, w)
+            .map_err(|e| Error::new("Failed to write report", e))
+    })?;
+
+    publish_file(&args.www_path, "report.geojson", |w| {
+        report::write_geojson(&osm_data.data, &issues, w)
+            .map_err(|e| Error::new("Failed to write report", e))
+    })?;
+
+    eprintln!(
+        "Found {} issues, wrote report.txt and report.geojson to {}",
+        issues.len(),
+        args.www_path.display()
+    );
     Ok(())
 }
+
+fn print_stats(path: &Path) -> Result<(), Error> {
+    let io_err = |e| Error::new(format!("Failed to read {}", path.display()), e);
+
+    let f = File::open(path).map_err(io_err)?;
+    let file_size = f.metadata().map_err(io_err)?.len();
+    let mut reader = BufReader::new(f);
+
+    // Sniff the format rather than trusting the extension
+    let header = reader.fill_buf().map_err(io_err)?;
+    let data: Data = if common::binary::is_binary(header) {
+        common::binary::read(reader).map_err(|e| Error::new("Failed to parse data", e))?
+    } else {
+        let mut json = Vec::new();
+        reader.read_to_end(&mut json).map_err(io_err)?;
+
+        let parse_err = |e| Error::new("Failed to parse data", e);
+        let version: DataVersion = serde_json::from_slice(&json).map_err(parse_err)?;
+        version
+            .validate()
+            .map_err(|e| Error::new("Invalid data", e))?;
+        serde_json::from_slice(&json).map_err(parse_err)?
+    };
+    data.validate().map_err(|e| Error::new("Invalid data", e))?;
+
+    stats::write_stats(&data, file_size, io::stdout().lock())
+        .map_err(|e| Error::new("Failed to write stats", e))
+}
+
+fn main() -> Result<(), Error> {
+    let cli_args: Vec<String> = std::env::args().collect();
+    let parse_err = |e| Error::new("Failed to parse arguments", e);
+
+    if let Some(path) = Args::stats_path(&cli_args).map_err(parse_err)? {
+        return print_stats(&path);
+    }
+
+    let args = Args::new(cli_args).map_err(parse_err)?;
+
+    if let Command::Report = args.command {
+        return write_report(&args);
+    }
+
+    if args.interval.is_none() && !args.watch {
+        return regenerate(&args);
+    }
+
+    let mut watcher = InputWatcher::new(args.builder().input_paths(), args.interval, args.watch)
+        .map_err(|e| Error::new("Failed to watch inputs", e))?;
+
+    loop {
+        watcher.wait_for_change();
+
+        // A bad input shouldn't take the daemon down, we'll try again when it changes
+        match regenerate(&args) {
+            Ok(()) => eprintln!("Published data to {}", args.www_path.display()),
+            Err(e) => eprintln!("Failed to regenerate data: {e:?}"),
+        }
+    }
+}
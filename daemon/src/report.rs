@@ -0,0 +1,529 @@
+use common::{Data, Node, Way};
+use ingest::OsmData;
+use serde_json::{json, Value};
+use std::{
+    collections::{HashMap, HashSet},
+    io::{self, Write},
+};
+
+/// Dead ends closer than this to a way they don't connect to are probably missing a junction
+const NEAR_MISS_DISTANCE_M: f32 = 5.0;
+
+/// Size of the cells way segments are bucketed into when looking for near misses, in decimicro
+/// degrees. Half a cell of latitude is comfortably larger than [`NEAR_MISS_DISTANCE_M`]. A cell
+/// of longitude shrinks towards the poles, see [`long_reach`]
+const GRID_CELL_SIZE: i32 = 4000;
+
+/// Meters per decimicro degree of latitude
+const METERS_PER_UNIT: f32 = 6_371_000.0 * std::f32::consts::PI / 180.0 / 10000000.0;
+
+const KNOWN_HIGHWAYS: &[&str] = &[
+    "motorway",
+    "trunk",
+    "primary",
+    "secondary",
+    "tertiary",
+    "unclassified",
+    "residential",
+    "motorway_link",
+    "trunk_link",
+    "primary_link",
+    "secondary_link",
+    "tertiary_link",
+    "living_street",
+    "service",
+    "pedestrian",
+    "track",
+    "bus_guideway",
+    "busway",
+    "escape",
+    "raceway",
+    "road",
+    "footway",
+    "bridleway",
+    "steps",
+    "corridor",
+    "path",
+    "cycleway",
+    "construction",
+    "proposed",
+    "elevator",
+    "platform",
+    "rest_area",
+    "services",
+    "abandoned",
+    "razed",
+];
+
+pub enum Issue {
+    /// A dead end `distance` meters from a way it doesn't connect to
+    NearMiss {
+        node: usize,
+        way: usize,
+        distance: f32,
+    },
+    SuspiciousTags {
+        way: usize,
+        reason: &'static str,
+    },
+    /// The same node, or two nodes at the same location, one after the other in a way
+    DuplicateNode {
+        way: usize,
+        node: usize,
+    },
+    /// Osm id of a highway left out of the data for having fewer than 2 usable nodes
+    ShortWay(i64),
+    MissingElevation(usize),
+}
+
+impl Issue {
+    fn kind(&self) -> &'static str {
+        match self {
+            Issue::NearMiss { .. } => "near_miss",
+            Issue::SuspiciousTags { .. } => "suspicious_tags",
+            Issue::DuplicateNode { .. } => "duplicate_node",
+            Issue::ShortWay(_) => "short_way",
+            Issue::MissingElevation(_) => "missing_elevation",
+        }
+    }
+
+    fn description(&self, data: &Data) -> String {
+        let way_url = |way: &usize| data.ways[*way].osm_url();
+        let node_url = |node: &usize| osm_node_url(&data.nodes[*node]);
+        match self {
+            Issue::NearMiss {
+                node,
+                way,
+                distance,
+            } => format!(
+                "Dead end {} is {distance:.1}m from {}, which it does not connect to",
+                node_url(node),
+                way_url(way)
+            ),
+            Issue::SuspiciousTags { way, reason } => format!("{}: {reason}", way_url(way)),
+            Issue::DuplicateNode { way, node } => format!(
+                "{} repeats {} or a node at the same location",
+                way_url(way),
+                node_url(node)
+            ),
+            Issue::ShortWay(osm_id) => format!(
+                "https://www.openstreetmap.org/way/{osm_id} has fewer than 2 nodes with a location"
+            ),
+            Issue::MissingElevation(node) => {
+                format!("{} is not covered by the elevation data", node_url(node))
+            }
+        }
+    }
+
+    fn geometry(&self, data: &Data) -> Value {
+        let point = |node: &usize| {
+            json!({
+                "type": "Point",
+                "coordinates": coordinates(&data.nodes[*node]),
+            })
+        };
+
+        match self {
+            Issue::NearMiss { node, .. }
+            | Issue::DuplicateNode { node, .. }
+            | Issue::MissingElevation(node) => point(node),
+            Issue::SuspiciousTags { way, .. } => json!({
+                "type": "LineString",
+                "coordinates": data.ways[*way]
+                    .nodes
+                    .iter()
+                    .map(|n| coordinates(&data.nodes[*n]))
+                    .collect::<Vec<_>>(),
+            }),
+            // We never saw enough of it to place it
+            Issue::ShortWay(_) => Value::Null,
+        }
+    }
+}
+
+fn osm_node_url(node: &Node) -> String {
+    format!("https://www.openstreetmap.org/node/{}", node.osm_id)
+}
+
+fn coordinates(node: &Node) -> [f64; 2] {
+    [node.long as f64 / 10000000.0, node.lat as f64 / 10000000.0]
+}
+
+/// Meters between `node` and the segment from `a` to `b`
+fn distance_to_segment(node: &Node, a: &Node, b: &Node) -> f32 {
+    // Flat projection around the node, fine over the few meters we care about
+    let long_scale = (node.lat as f32 / 10000000.0).to_radians().cos();
+    let project = |n: &Node| {
+        (
+            (n.long - node.long) as f32 * long_scale * METERS_PER_UNIT,
+            (n.lat - node.lat) as f32 * METERS_PER_UNIT,
+        )
+    };
+
+    let (ax, ay) = project(a);
+    let (bx, by) = project(b);
+    let (dx, dy) = (bx - ax, by - ay);
+    let len_sq = dx * dx + dy * dy;
+    let t = if len_sq > 0.0 {
+        ((-ax * dx - ay * dy) / len_sq).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+
+    let (x, y) = (ax + dx * t, ay + dy * t);
+    (x * x + y * y).sqrt()
+}
+
+/// How many cells of longitude either side of a segment sample a node within
+/// [`NEAR_MISS_DISTANCE_M`] of it can be. Samples are half a cell apart, so this is one up to about
+/// 77 degrees north or south and grows as a cell of longitude shrinks past that
+fn long_reach(lat: i32) -> i32 {
+    // Nodes filed around this sample may be up to a cell closer to the pole than it is
+    let lat = lat
+        .unsigned_abs()
+        .saturating_add(GRID_CELL_SIZE as u32)
+        .min(900000000);
+    let long_scale = (lat as f32 / 10000000.0).to_radians().cos().max(0.001);
+    let near_miss = NEAR_MISS_DISTANCE_M / (long_scale * METERS_PER_UNIT);
+    (0.5 + near_miss / GRID_CELL_SIZE as f32).ceil() as i32
+}
+
+fn grid_cell(lat: i32, long: i32) -> (i32, i32) {
+    (
+        lat.div_euclid(GRID_CELL_SIZE),
+        long.div_euclid(GRID_CELL_SIZE),
+    )
+}
+
+/// Dead ends that come close to a way without connecting to it. Mapping that stops just short of
+/// the road it was meant to join is one of the most common ways for a network to fall apart
+fn near_misses(data: &Data) -> Vec<Issue> {
+    let mut incident_edges = vec![0u32; data.nodes.len()];
+    let mut node_way = vec![usize::MAX; data.nodes.len()];
+    for edge in &data.edges {
+        incident_edges[edge.from] += 1;
+        incident_edges[edge.to] += 1;
+        node_way[edge.from] = edge.way;
+        node_way[edge.to] = edge.way;
+    }
+
+    // (way, index of the segment's first node in the way). Segments are sampled at least twice a
+    // cell, so every point on them is within half a cell of a sample, and filed under the cells
+    // around each sample. A node near a segment then finds it in the node's own cell
+    let mut grid: HashMap<(i32, i32), Vec<(usize, usize)>> = HashMap::new();
+    let mut cells = Vec::new();
+    for (way_idx, way) in data.ways.iter().enumerate() {
+        for (i, pair) in way.nodes.windows(2).enumerate() {
+            let (a, b) = (&data.nodes[pair[0]], &data.nodes[pair[1]]);
+            let (d_lat, d_long) = (b.lat as i64 - a.lat as i64, b.long as i64 - a.long as i64);
+            let steps = d_lat.abs().max(d_long.abs()) * 2 / GRID_CELL_SIZE as i64 + 1;
+
+            cells.clear();
+            for step in 0..=steps {
+                let sample_lat = (a.lat as i64 + d_lat * step / steps) as i32;
+                let sample_long = (a.long as i64 + d_long * step / steps) as i32;
+                let (lat, long) = grid_cell(sample_lat, sample_long);
+                let long_reach = long_reach(sample_lat);
+                for offset_lat in -1..=1 {
+                    for offset_long in -long_reach..=long_reach {
+                        cells.push((lat + offset_lat, long + offset_long));
+                    }
+                }
+            }
+
+            cells.sort_unstable();
+            cells.dedup();
+            for cell in &cells {
+                grid.entry(*cell).or_default().push((way_idx, i));
+            }
+        }
+    }
+
+    let mut ret = Vec::new();
+    for (node_idx, node) in data.nodes.iter().enumerate() {
+        if incident_edges[node_idx] != 1 {
+            continue;
+        }
+
+        let own_way = &data.ways[node_way[node_idx]];
+        let own_nodes: HashSet<usize> = own_way.nodes.iter().copied().collect();
+        // Ways joining ours anywhere are reachable, a dead end next to them is just a short stub
+        let connected = |way: &Way| way.nodes.iter().any(|n| own_nodes.contains(n));
+
+        let mut connected_ways = HashMap::new();
+        let mut closest: Option<(usize, f32)> = None;
+        for (way_idx, i) in grid
+            .get(&grid_cell(node.lat, node.long))
+            .into_iter()
+            .flatten()
+        {
+            let way = &data.ways[*way_idx];
+            if *connected_ways
+                .entry(*way_idx)
+                .or_insert_with(|| connected(way))
+            {
+                continue;
+            }
+
+            let distance = distance_to_segment(
+                node,
+                &data.nodes[way.nodes[*i]],
+                &data.nodes[way.nodes[*i + 1]],
+            );
+            if distance < NEAR_MISS_DISTANCE_M && closest.is_none_or(|(_, d)| distance < d) {
+                closest = Some((*way_idx, distance));
+            }
+        }
+
+        if let Some((way, distance)) = closest {
+            ret.push(Issue::NearMiss {
+                node: node_idx,
+                way,
+                distance,
+            });
+        }
+    }
+
+    // Closest first, those are the most likely to be missing junctions
+    ret.sort_by(|a, b| match (a, b) {
+        (Issue::NearMiss { distance: a, .. }, Issue::NearMiss { distance: b, .. }) => {
+            a.total_cmp(b)
+        }
+        _ => std::cmp::Ordering::Equal,
+    });
+    ret
+}
+
+/// Tag combinations that are contradictory or that nothing consuming osm data would understand
+fn suspicious_tags(data: &Data, way: &Way) -> Vec<&'static str> {
+    let tag = |key| data.tag_value(way, key);
+    let flagged = |key| tag(key).is_some_and(|v| v != "no");
+    let allowed = |key| tag(key).is_some_and(|v| matches!(v, "yes" | "designated" | "permissive"));
+
+    let mut ret = Vec::new();
+    let highway = tag("highway").unwrap_or_default();
+
+    if !KNOWN_HIGHWAYS.contains(&highway) {
+        ret.push("Unknown highway value");
+    }
+
+    if flagged("bridge") && flagged("tunnel") {
+        ret.push("Both a bridge and a tunnel");
+    }
+
+    let layer = tag("layer").map(|v| v.trim().parse::<i32>());
+    match layer {
+        Some(Err(_)) => ret.push("Layer is not a number"),
+        Some(Ok(layer)) if !(-5..=5).contains(&layer) => ret.push("Layer out of range"),
+        Some(Ok(layer)) if layer < 0 && flagged("bridge") => ret.push("Bridge on a negative layer"),
+        Some(Ok(layer)) if layer > 0 && flagged("tunnel") => ret.push("Tunnel on a positive layer"),
+        _ => (),
+    }
+
+    if matches!(highway, "motorway" | "motorway_link") && (allowed("foot") || allowed("bicycle")) {
+        ret.push("Motorway open to pedestrians or cyclists");
+    }
+
+    let is_path = matches!(
+        highway,
+        "footway" | "path" | "cycleway" | "steps" | "pedestrian" | "bridleway"
+    );
+    if is_path && tag("maxspeed").is_some() {
+        ret.push("Speed limit on a path");
+    }
+
+    if let Some(maxspeed) = tag("maxspeed") {
+        let numeric = maxspeed
+            .split_whitespace()
+            .next()
+            .is_some_and(|v| v.parse::<f32>().is_ok());
+        // Implicit limits look like CA:urban
+        let symbolic =
+            matches!(maxspeed, "none" | "walk" | "signals" | "variable") || maxspeed.contains(':');
+        if !numeric && !symbolic {
+            ret.push("Unrecognized maxspeed");
+        }
+    }
+
+    if let Some(oneway) = tag("oneway") {
+        if !matches!(
+            oneway,
+            "yes" | "no" | "-1" | "1" | "true" | "false" | "reversible" | "alternating"
+        ) {
+            ret.push("Unrecognized oneway");
+        }
+    }
+
+    ret
+}
+
+fn duplicate_nodes(data: &Data, way_idx: usize, way: &Way) -> Vec<Issue> {
+    way.nodes
+        .windows(2)
+        .filter(|pair| {
+            let (a, b) = (&data.nodes[pair[0]], &data.nodes[pair[1]]);
+            pair[0] == pair[1] || (a.lat == b.lat && a.long == b.long)
+        })
+        .map(|pair| Issue::DuplicateNode {
+            way: way_idx,
+            node: pair[1],
+        })
+        .collect()
+}
+
+/// Find problems in the network worth fixing upstream in osm, most useful first
+pub fn analyze(osm_data: &OsmData) -> Vec<Issue> {
+    let data = &osm_data.data;
+    let mut ret = near_misses(data);
+
+    for (way_idx, way) in data.ways.iter().enumerate() {
+        ret.extend(
+            suspicious_tags(data, way)
+                .into_iter()
+                .map(|reason| Issue::SuspiciousTags {
+                    way: way_idx,
+                    reason,
+                }),
+        );
+    }
+
+    for (way_idx, way) in data.ways.iter().enumerate() {
+        ret.extend(duplicate_nodes(data, way_idx, way));
+    }
+
+    ret.extend(osm_data.short_ways.iter().map(|id| Issue::ShortWay(*id)));
+
+    // Islands may have been dropped since the osm ids were recorded
+    let node_indices: HashMap<i64, usize> = data
+        .nodes
+        .iter()
+        .enumerate()
+        .map(|(i, node)| (node.osm_id, i))
+        .collect();
+    ret.extend(
+        osm_data
+            .unmeasured_nodes
+            .iter()
+            .filter_map(|id| node_indices.get(id))
+            .map(|node| Issue::MissingElevation(*node)),
+    );
+
+    ret
+}
+
+pub fn write_text<W: Write>(data: &Data, issues: &[Issue], mut w: W) -> io::Result<()> {
+    const SECTIONS: &[(&str, &str)] = &[
+        ("near_miss", "Dead ends near ways they don't connect to"),
+        ("suspicious_tags", "Suspicious tags"),
+        ("duplicate_node", "Duplicate consecutive nodes"),
+        ("short_way", "Ways with fewer than 2 nodes"),
+        ("missing_elevation", "Nodes without elevation"),
+    ];
+
+    writeln!(w, "Network quality report")?;
+    for source in &data.metadata.source_files {
+        writeln!(w, "Source: {source}")?;
+    }
+    writeln!(w)?;
+
+    for (kind, title) in SECTIONS {
+        let count = issues.iter().filter(|i| i.kind() == *kind).count();
+        writeln!(w, "{title}: {count}")?;
+    }
+
+    for (kind, title) in SECTIONS {
+        writeln!(w, "\n{title}\n")?;
+        for issue in issues.iter().filter(|i| i.kind() == *kind) {
+            writeln!(w, "{}", issue.description(data))?;
+        }
+    }
+
+    Ok(())
+}
+
+pub fn write_geojson<W: Write>(data: &Data, issues: &[Issue], w: W) -> serde_json::Result<()> {
+    let features: Vec<Value> = issues
+        .iter()
+        .map(|issue| {
+            json!({
+                "type": "Feature",
+                "geometry": issue.geometry(data),
+                "properties": {
+                    "issue": issue.kind(),
+                    "description": issue.description(data),
+                },
+            })
+        })
+        .collect();
+
+    serde_json::to_writer(
+        w,
+        &json!({
+            "type": "FeatureCollection",
+            "features": features,
+        }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A way running north from `lat`, and a dead end `gap_m` meters east of its middle
+    fn near_miss_at(lat: f32, gap_m: f32) -> Data {
+        let lat = (lat * 10000000.0) as i32;
+        let long = 1000;
+        let units_east = |m: f32| {
+            let long_scale = (lat as f32 / 10000000.0).to_radians().cos();
+            long + (m / (long_scale * METERS_PER_UNIT)) as i32
+        };
+
+        let node = |lat, long| Node {
+            osm_id: 0,
+            lat,
+            long,
+            height: None,
+        };
+        let way = |nodes| Way {
+            osm_id: 0,
+            tags: Vec::new(),
+            nodes,
+        };
+
+        let mut data = Data {
+            version: common::DATA_VERSION,
+            metadata: Default::default(),
+            strings: Vec::new(),
+            nodes: vec![
+                node(lat, long),
+                node(lat + 2000, long),
+                node(lat + 1000, units_east(gap_m)),
+                node(lat + 1000, units_east(gap_m + 20.0)),
+            ],
+            ways: vec![way(vec![0, 1]), way(vec![2, 3])],
+            edges: Vec::new(),
+        };
+        data.edges = ingest::edges::build_edges(&data);
+        data
+    }
+
+    #[test]
+    fn near_misses_at_any_latitude() {
+        for lat in [0.0, 45.0, 80.0, 85.0, 88.0, -89.0] {
+            let issues = near_misses(&near_miss_at(lat, 4.5));
+            assert!(
+                matches!(
+                    issues[..],
+                    [Issue::NearMiss {
+                        node: 2,
+                        way: 0,
+                        distance
+                    }] if (distance - 4.5).abs() < 0.1
+                ),
+                "Missed the dead end at {lat}"
+            );
+
+            assert!(near_misses(&near_miss_at(lat, 6.0)).is_empty());
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use common::{Data, Edge, Node, Tag, Way};
+use std::{
+    collections::{HashMap, HashSet},
+    io::{self, Write},
+    mem::size_of,
+};
+
+#[derive(Default)]
+struct HighwayStats {
+    ways: usize,
+    length_m: f64,
+}
+
+fn format_bytes(bytes: usize) -> String {
+    const UNITS: &[&str] = &["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{value:.1} {}", UNITS[unit])
+}
+
+/// Heap and inline size of the data once loaded, ignoring allocator overhead and spare capacity
+fn memory_footprint(data: &Data) -> usize {
+    let strings: usize = data
+        .strings
+        .iter()
+        .map(|s| size_of::<String>() + s.len())
+        .sum();
+    let ways: usize = data
+        .ways
+        .iter()
+        .map(|w| {
+            size_of::<Way>() + w.tags.len() * size_of::<Tag>() + w.nodes.len() * size_of::<usize>()
+        })
+        .sum();
+    let edges: usize = data
+        .edges
+        .iter()
+        .map(|e| size_of::<Edge>() + e.shape.len() * size_of::<usize>())
+        .sum();
+
+    size_of::<Data>() + strings + data.nodes.len() * size_of::<Node>() + ways + edges
+}
+
+/// Summary of what a dataset contains. `file_size` is the size of the file it was read from
+pub fn write_stats<W: Write>(data: &Data, file_size: u64, mut w: W) -> io::Result<()> {
+    let mut highways: HashMap<&str, HighwayStats> = HashMap::new();
+    for way in &data.ways {
+        let highway = data.tag_value(way, "highway").unwrap_or("(none)");
+        highways.entry(highway).or_default().ways += 1;
+    }
+    for edge in &data.edges {
+        let way = &data.ways[edge.way];
+        let highway = data.tag_value(way, "highway").unwrap_or("(none)");
+        highways.entry(highway).or_default().length_m += edge.length as f64;
+    }
+
+    let mut highways: Vec<_> = highways.into_iter().collect();
+    highways.sort_by(|a, b| b.1.length_m.total_cmp(&a.1.length_m).then(a.0.cmp(b.0)));
+
+    let heights: Vec<f32> = data.nodes.iter().filter_map(|n| n.height).collect();
+    // Nodes only referenced by ways that were left out form components of their own, don't count
+    // those
+    let node_components = data.components();
+    let components: HashSet<u32> = data
+        .ways
+        .iter()
+        .map(|way| node_components[way.nodes[0]])
+        .collect();
+
+    writeln!(w, "Data version: {}", data.version)?;
+    for source in &data.metadata.source_files {
+        writeln!(w, "Source: {source}")?;
+    }
+    writeln!(w)?;
+
+    writeln!(w, "Nodes: {}", data.nodes.len())?;
+    writeln!(w, "Ways: {}", data.ways.len())?;
+    writeln!(w, "Routing edges: {}", data.edges.len())?;
+    writeln!(w, "Connected components: {}", components.len())?;
+    writeln!(w)?;
+
+    let bb = &data.metadata.bounding_box;
+    let degrees = |v: i32| v as f64 / 10000000.0;
+    writeln!(
+        w,
+        "Bounding box: {:.7},{:.7} to {:.7},{:.7} (lat,long)",
+        degrees(bb.min_lat),
+        degrees(bb.min_long),
+        degrees(bb.max_lat),
+        degrees(bb.max_long)
+    )?;
+
+    let coverage = match data.nodes.len() {
+        0 => 0.0,
+        n => heights.len() as f64 / n as f64 * 100.0,
+    };
+    writeln!(w, "Elevation coverage: {coverage:.1}% of nodes")?;
+    let min_height = heights.iter().copied().reduce(f32::min);
+    let max_height = heights.iter().copied().reduce(f32::max);
+    if let (Some(min), Some(max)) = (min_height, max_height) {
+        writeln!(w, "Height range: {min:.1}m to {max:.1}m")?;
+    }
+    writeln!(w)?;
+
+    writeln!(w, "File size: {}", format_bytes(file_size as usize))?;
+    writeln!(
+        w,
+        "Memory footprint: {}",
+        format_bytes(memory_footprint(data))
+    )?;
+    writeln!(w)?;
+
+    let name_width = highways
+        .iter()
+        .map(|(name, _)| name.len())
+        .max()
+        .unwrap_or(0)
+        .max("highway".len());
+    writeln!(
+        w,
+        "{:name_width$}  {:>8}  {:>12}",
+        "highway", "ways", "length (km)"
+    )?;
+    for (name, stats) in &highways {
+        writeln!(
+            w,
+            "{name:name_width$}  {:>8}  {:>12.2}",
+            stats.ways,
+            stats.length_m / 1000.0
+        )?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,174 @@
+use common::{distance_m, Access, Data, Edge, Node, SpeedClass, Way};
+
+const EVERYONE: Access = Access {
+    foot: true,
+    bicycle: true,
+    motor_vehicle: true,
+};
+
+const NOBODY: Access = Access {
+    foot: false,
+    bicycle: false,
+    motor_vehicle: false,
+};
+
+/// Speed class and access implied by the highway tag alone
+fn highway_defaults(highway: &str) -> (SpeedClass, Access) {
+    let foot_only = Access {
+        foot: true,
+        ..NOBODY
+    };
+
+    match highway {
+        "motorway" | "motorway_link" => (
+            SpeedClass::Motorway,
+            Access {
+                motor_vehicle: true,
+                ..NOBODY
+            },
+        ),
+        "trunk" | "trunk_link" | "primary" | "primary_link" => (SpeedClass::Arterial, EVERYONE),
+        "secondary" | "secondary_link" | "tertiary" | "tertiary_link" => {
+            (SpeedClass::Collector, EVERYONE)
+        }
+        "residential" | "unclassified" | "living_street" | "road" => (SpeedClass::Local, EVERYONE),
+        "service" => (SpeedClass::Service, EVERYONE),
+        "track" => (SpeedClass::Track, EVERYONE),
+        "cycleway" | "path" => (
+            SpeedClass::Path,
+            Access {
+                foot: true,
+                bicycle: true,
+                ..NOBODY
+            },
+        ),
+        // Not built yet or not there anymore
+        "construction" | "proposed" | "abandoned" | "razed" => (SpeedClass::Path, NOBODY),
+        // footway, pedestrian, steps, bridleway and anything we don't know about
+        _ => (SpeedClass::Path, foot_only),
+    }
+}
+
+/// Whether an access tag value lets the general public through. None for values that say nothing
+/// either way, which leave the default in place
+fn access_allowed(value: &str) -> Option<bool> {
+    match value {
+        "yes" | "designated" | "permissive" | "destination" | "customers" | "delivery"
+        | "official" => Some(true),
+        "no" | "private" | "agricultural" | "forestry" | "use_sidepath" => Some(false),
+        _ => None,
+    }
+}
+
+fn way_access(data: &Data, way: &Way, mut access: Access) -> Access {
+    let tag = |key| data.tag_value(way, key).and_then(access_allowed);
+
+    // From least to most specific, so that e.g. access=no + bicycle=yes allows bikes
+    if let Some(allowed) = tag("access") {
+        access = Access {
+            foot: allowed,
+            bicycle: allowed,
+            motor_vehicle: allowed,
+        };
+    }
+    if let Some(allowed) = tag("vehicle") {
+        access.bicycle = allowed;
+        access.motor_vehicle = allowed;
+    }
+    if let Some(allowed) = tag("foot") {
+        access.foot = allowed;
+    }
+    if let Some(allowed) = tag("bicycle") {
+        access.bicycle = allowed;
+    }
+    if let Some(allowed) = tag("motor_vehicle") {
+        access.motor_vehicle = allowed;
+    }
+
+    access
+}
+
+/// Net rise over run between two nodes `length` meters apart along a way
+fn net_grade(from: &Node, to: &Node, length: f32) -> f32 {
+    match (from.height, to.height) {
+        (Some(a), Some(b)) if length > 0.0 => (b - a) / length,
+        _ => 0.0,
+    }
+}
+
+/// One edge per pair of consecutive nodes in each way, with the attributes that only depend on
+/// the way and node positions. Climbing is filled in separately once heights are final
+pub fn build_edges(data: &Data) -> Vec<Edge> {
+    let mut ret = Vec::new();
+    for (way_idx, way) in data.ways.iter().enumerate() {
+        let highway = data.tag_value(way, "highway").unwrap_or_default();
+        let (speed_class, access) = highway_defaults(highway);
+        let access = way_access(data, way, access);
+
+        for pair in way.nodes.windows(2) {
+            // Repeated nodes in a way lead nowhere
+            if pair[0] == pair[1] {
+                continue;
+            }
+
+            let (from, to) = (&data.nodes[pair[0]], &data.nodes[pair[1]]);
+            let length = distance_m(from, to);
+            let grade = net_grade(from, to, length);
+
+            ret.push(Edge {
+                way: way_idx,
+                from: pair[0],
+                to: pair[1],
+                shape: Vec::new(),
+                length,
+                grade,
+                speed_class,
+                access,
+                ascent: 0.0,
+                descent: 0.0,
+                max_grade: 0.0,
+            });
+        }
+    }
+
+    ret
+}
+
+/// Merge runs of edges along the same way whose shared nodes connect nothing else. Those nodes only
+/// describe the way's geometry, routing over them one at a time just makes the search graph larger.
+/// Closed ways keep their last junction so that no edge starts and ends at the same node. Expects
+/// edges in way order, as [`build_edges`] produces them
+pub fn collapse_chains(data: &Data, edges: Vec<Edge>) -> Vec<Edge> {
+    let mut incident_edges = vec![0u32; data.nodes.len()];
+    for edge in &edges {
+        incident_edges[edge.from] += 1;
+        incident_edges[edge.to] += 1;
+    }
+
+    let mut ret: Vec<Edge> = Vec::new();
+    for edge in edges {
+        match ret.last_mut() {
+            Some(last)
+                if last.way == edge.way
+                    && last.to == edge.from
+                    && last.from != edge.to
+                    && incident_edges[edge.from] == 2 =>
+            {
+                last.shape.push(edge.from);
+                last.shape.extend(edge.shape);
+                last.to = edge.to;
+                last.length += edge.length;
+                last.ascent += edge.ascent;
+                last.descent += edge.descent;
+                last.max_grade = last.max_grade.max(edge.max_grade);
+            }
+            _ => ret.push(edge),
+        }
+    }
+
+    for edge in &mut ret {
+        edge.grade = net_grade(&data.nodes[edge.from], &data.nodes[edge.to], edge.length);
+    }
+
+    ret
+}
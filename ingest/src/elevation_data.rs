@@ -0,0 +1,1021 @@
+use std::{
+    error::Error,
+    fmt,
+    io::{self, BufRead},
+    iter::Peekable,
+    path::Path,
+    str::FromStr,
+};
+
+use rayon::prelude::*;
+
+pub mod geotiff;
+pub mod hgt;
+pub mod projection;
+
+use projection::Projection;
+
+/// Anything heights can be looked up in
+pub trait ElevationSource: Send + Sync {
+    /// Size of a cell in degrees, smaller is more detailed
+    fn resolution(&self) -> f32;
+    fn set_interpolation(&mut self, interpolation: Interpolation);
+    fn height_at_lat_long(&self, lat: f32, long: f32) -> Option<f32>;
+}
+
+/// Raster formats we know how to read, identified by file extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ElevationFormat {
+    /// Esri ASCII grid
+    Asc,
+    /// Gzip compressed Esri ASCII grid
+    AscGz,
+    /// SRTM height tile
+    Hgt,
+    GeoTiff,
+}
+
+impl ElevationFormat {
+    pub fn from_path(path: &Path) -> Option<ElevationFormat> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "asc" => Some(ElevationFormat::Asc),
+            "gz" => match ElevationFormat::from_path(&path.with_extension(""))? {
+                ElevationFormat::Asc => Some(ElevationFormat::AscGz),
+                _ => None,
+            },
+            "hgt" => Some(ElevationFormat::Hgt),
+            "tif" | "tiff" => Some(ElevationFormat::GeoTiff),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Point {
+    x: f64,
+    y: f64,
+}
+
+/// How to sample the grid between cell centers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Interpolation {
+    /// Value of the cell containing the point
+    #[default]
+    Nearest,
+    /// Linear blend of the 4 surrounding cell centers
+    Bilinear,
+    /// Catmull-Rom spline through the 16 surrounding cell centers
+    Bicubic,
+}
+
+impl FromStr for Interpolation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "nearest" => Ok(Interpolation::Nearest),
+            "bilinear" => Ok(Interpolation::Bilinear),
+            "bicubic" => Ok(Interpolation::Bicubic),
+            _ => Err(s.to_string()),
+        }
+    }
+}
+
+/// A single raster
+pub struct ElevationGrid {
+    step_x: f64,
+    step_y: f64,
+    row_length: usize,
+    tl_corner: Point,
+    nodata_val: f32,
+    data: Vec<f32>,
+    interpolation: Interpolation,
+    projection: Projection,
+}
+
+impl ElevationSource for ElevationGrid {
+    fn resolution(&self) -> f32 {
+        self.projection
+            .distance_in_degrees(self.step_x.max(self.step_y) as f32)
+    }
+
+    fn set_interpolation(&mut self, interpolation: Interpolation) {
+        self.interpolation = interpolation;
+    }
+
+    fn height_at_lat_long(&self, lat: f32, long: f32) -> Option<f32> {
+        self.height_at_lat_long(lat, long)
+    }
+}
+
+impl ElevationGrid {
+    /// Lookups are made in WGS84, set this for rasters that are in a different system
+    pub fn set_projection(&mut self, projection: Projection) {
+        self.projection = projection;
+    }
+
+    fn num_rows(&self) -> usize {
+        self.data.len() / self.row_length
+    }
+
+    /// Value of the cell at the given column/row. Indices outside the grid are clamped to the
+    /// closest edge
+    fn cell(&self, x: isize, y: isize) -> Option<f32> {
+        let x = x.clamp(0, self.row_length as isize - 1) as usize;
+        let y = y.clamp(0, self.num_rows() as isize - 1) as usize;
+
+        let ret = self.data[y * self.row_length + x];
+
+        if ret.is_nan() || (f32::abs(ret - self.nodata_val) < 0.001) {
+            return None;
+        }
+
+        Some(ret)
+    }
+
+    pub fn height_at_lat_long(&self, lat: f32, long: f32) -> Option<f32> {
+        // Position in units of cells, where integer coordinates are cell centers
+        let (x, y) = self.projection.forward(lat as f64, long as f64);
+        let x = (x - self.tl_corner.x) / self.step_x - 0.5;
+        let y = (self.tl_corner.y - y) / self.step_y - 0.5;
+
+        // Written so that NaN ends up out of range as well
+        let in_range = x >= -0.5
+            && x < self.row_length as f64 - 0.5
+            && y >= -0.5
+            && y < self.num_rows() as f64 - 0.5;
+        if !in_range {
+            return None;
+        }
+
+        match self.interpolation {
+            Interpolation::Nearest => self.cell(x.round() as isize, y.round() as isize),
+            Interpolation::Bilinear => self.bilinear(x, y),
+            Interpolation::Bicubic => self.bicubic(x, y).or_else(|| self.bilinear(x, y)),
+        }
+    }
+
+    fn bilinear(&self, x: f64, y: f64) -> Option<f32> {
+        let x0 = x.floor();
+        let y0 = y.floor();
+        let tx = x - x0;
+        let ty = y - y0;
+        let (x0, y0) = (x0 as isize, y0 as isize);
+
+        let samples = [
+            (self.cell(x0, y0), (1.0 - tx) * (1.0 - ty)),
+            (self.cell(x0 + 1, y0), tx * (1.0 - ty)),
+            (self.cell(x0, y0 + 1), (1.0 - tx) * ty),
+            (self.cell(x0 + 1, y0 + 1), tx * ty),
+        ];
+
+        // Nodata neighbors are left out and the remaining weights renormalized, so a single
+        // missing cell doesn't punch a hole the size of 4 cells into the data
+        let mut total = 0.0;
+        let mut total_weight = 0.0;
+        for (v, weight) in samples {
+            if let Some(v) = v {
+                total += v as f64 * weight;
+                total_weight += weight;
+            }
+        }
+
+        if total_weight <= 0.0 {
+            return None;
+        }
+
+        Some((total / total_weight) as f32)
+    }
+
+    /// Returns None if any of the 16 cells involved is nodata, in which case callers should fall
+    /// back to a cheaper method
+    fn bicubic(&self, x: f64, y: f64) -> Option<f32> {
+        // Outside the outermost cell centers the spline would run through clamped copies of the
+        // edge and overshoot, keep it flat there like the other methods
+        let x = x.clamp(0.0, (self.row_length - 1) as f64);
+        let y = y.clamp(0.0, (self.num_rows() - 1) as f64);
+
+        let x0 = x.floor();
+        let y0 = y.floor();
+        let tx = x - x0;
+        let ty = y - y0;
+        let (x0, y0) = (x0 as isize, y0 as isize);
+
+        let mut rows = [0.0; 4];
+        for (row, dy) in rows.iter_mut().zip(-1..=2) {
+            let mut cols = [0.0; 4];
+            for (col, dx) in cols.iter_mut().zip(-1..=2) {
+                *col = self.cell(x0 + dx, y0 + dy)? as f64;
+            }
+            *row = catmull_rom(cols, tx);
+        }
+
+        Some(catmull_rom(rows, ty) as f32)
+    }
+}
+
+/// Several rasters treated as one. Lookups are answered by the finest resolution grid that has
+/// data at the requested position, so overlapping tiles of different quality can be mixed
+pub struct ElevationData {
+    sources: Vec<Box<dyn ElevationSource>>,
+}
+
+impl ElevationData {
+    pub fn new(mut sources: Vec<Box<dyn ElevationSource>>) -> ElevationData {
+        sources.sort_by(|a, b| a.resolution().total_cmp(&b.resolution()));
+        ElevationData { sources }
+    }
+
+    pub fn set_interpolation(&mut self, interpolation: Interpolation) {
+        for source in &mut self.sources {
+            source.set_interpolation(interpolation);
+        }
+    }
+
+    pub fn height_at_lat_long(&self, lat: f32, long: f32) -> Option<f32> {
+        self.sources
+            .iter()
+            .find_map(|s| s.height_at_lat_long(lat, long))
+    }
+}
+
+/// Interpolates between p[1] and p[2], t in [0, 1]
+fn catmull_rom(p: [f64; 4], t: f64) -> f64 {
+    p[1] + 0.5
+        * t
+        * (p[2] - p[0]
+            + t * (2.0 * p[0] - 5.0 * p[1] + 4.0 * p[2] - p[3]
+                + t * (3.0 * (p[1] - p[2]) + p[3] - p[0])))
+}
+
+/// Used when the header doesn't specify one
+const DEFAULT_NODATA: f32 = -9999.0;
+
+#[derive(Debug)]
+struct Header {
+    rows: usize,
+    cols: usize,
+    /// Lower left corner of the lower left cell
+    xllcorner: f64,
+    yllcorner: f64,
+    dx: f64,
+    dy: f64,
+    nodata: f32,
+}
+
+/// Resolve a position given either as a cell corner or a cell center to the corner
+fn corner_from(
+    corner: Option<f64>,
+    center: Option<f64>,
+    cellsize: f64,
+    names: (&'static str, &'static str),
+) -> Result<f64, HeaderParseError> {
+    match (corner, center) {
+        (Some(v), None) => Ok(v),
+        (None, Some(v)) => Ok(v - cellsize / 2.0),
+        (Some(_), Some(_)) => Err(HeaderParseError::ConflictingFields(names.0, names.1)),
+        (None, None) => Err(HeaderParseError::MissingFields(names.0)),
+    }
+}
+
+impl TryFrom<HeaderBuilder> for Header {
+    type Error = HeaderParseError;
+
+    fn try_from(value: HeaderBuilder) -> Result<Self, Self::Error> {
+        use HeaderParseError as E;
+
+        let (dx, dy) = match (value.cellsize, value.dx, value.dy) {
+            (Some(v), None, None) => (v, v),
+            (None, Some(dx), Some(dy)) => (dx, dy),
+            (Some(_), Some(_), _) => return Err(E::ConflictingFields("cellsize", "dx")),
+            (Some(_), None, Some(_)) => return Err(E::ConflictingFields("cellsize", "dy")),
+            (None, None, None) => return Err(E::MissingFields("cellsize")),
+            (None, Some(_), None) => return Err(E::MissingFields("dy")),
+            (None, None, Some(_)) => return Err(E::MissingFields("dx")),
+        };
+
+        Ok(Header {
+            rows: value.rows.ok_or(E::MissingFields("nrows"))?,
+            cols: value.cols.ok_or(E::MissingFields("ncols"))?,
+            xllcorner: corner_from(
+                value.xllcorner,
+                value.xllcenter,
+                dx,
+                ("xllcorner", "xllcenter"),
+            )?,
+            yllcorner: corner_from(
+                value.yllcorner,
+                value.yllcenter,
+                dy,
+                ("yllcorner", "yllcenter"),
+            )?,
+            dx,
+            dy,
+            nodata: value.nodata.unwrap_or(DEFAULT_NODATA),
+        })
+    }
+}
+
+#[derive(Default)]
+struct HeaderBuilder {
+    rows: Option<usize>,
+    cols: Option<usize>,
+    xllcorner: Option<f64>,
+    yllcorner: Option<f64>,
+    xllcenter: Option<f64>,
+    yllcenter: Option<f64>,
+    cellsize: Option<f64>,
+    dx: Option<f64>,
+    dy: Option<f64>,
+    nodata: Option<f32>,
+}
+
+#[derive(Debug)]
+pub enum HeaderParseError {
+    Io(io::Error),
+    MissingKey(usize),
+    MissingValue(usize),
+    InvalidKey(String),
+    InvalidInt(std::num::ParseIntError),
+    InvalidFloat(std::num::ParseFloatError),
+    MissingFields(&'static str),
+    ConflictingFields(&'static str, &'static str),
+    ExtraHeaderValue(usize),
+}
+
+impl fmt::Display for HeaderParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use HeaderParseError::*;
+        match self {
+            Io(_) => write!(f, "Failed to read header data from file"),
+            MissingKey(line) => write!(f, "Missing header key, line {line}"),
+            MissingValue(line) => write!(f, "Missing header value, line {line}"),
+            InvalidKey(value) => write!(f, "Invalid header key: {value}"),
+            InvalidInt(_) => write!(f, "Invalid header value"),
+            InvalidFloat(_) => write!(f, "Invalid header value"),
+            MissingFields(field) => write!(
+                f,
+                "Not all required header fields are provided, missing: {field}"
+            ),
+            ConflictingFields(a, b) => write!(f, "Header cannot contain both {a} and {b}"),
+            ExtraHeaderValue(line) => write!(f, "Header contained too much data, line {line}"),
+        }
+    }
+}
+
+impl Error for HeaderParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        use HeaderParseError::*;
+        match self {
+            Io(s) => Some(s),
+            InvalidInt(s) => Some(s),
+            InvalidFloat(s) => Some(s),
+            MissingKey(_)
+            | MissingValue(_)
+            | InvalidKey(_)
+            | ExtraHeaderValue(_)
+            | MissingFields(_)
+            | ConflictingFields(..) => None,
+        }
+    }
+}
+
+fn parse_header<T>(lines: &mut Peekable<io::Lines<T>>) -> Result<Header, HeaderParseError>
+where
+    T: BufRead,
+{
+    let mut ret = HeaderBuilder::default();
+
+    // Peek so that the first data line is left in the iterator for the caller
+    let mut line_num = 0;
+    while let Some(line) = lines.peek() {
+        let line = match line {
+            Ok(v) => v,
+            // Have to actually consume the value to return it
+            Err(_) => return Err(HeaderParseError::Io(lines.next().unwrap().unwrap_err())),
+        };
+
+        let mut line_it = line.split_whitespace();
+
+        let key = match line_it.next() {
+            Some(v) => v.to_lowercase(),
+            None => return Err(HeaderParseError::MissingKey(line_num)),
+        };
+
+        // Most keys are optional, so the header has no fixed length. It ends where the numbers
+        // start
+        if key.parse::<f32>().is_ok() {
+            break;
+        }
+
+        let value = match line_it.next() {
+            Some(v) => v.to_lowercase(),
+            None => return Err(HeaderParseError::MissingValue(line_num)),
+        };
+
+        if line_it.next().is_some() {
+            return Err(HeaderParseError::ExtraHeaderValue(line_num));
+        }
+
+        let parse_float = |v: &str| v.parse::<f64>().map_err(HeaderParseError::InvalidFloat);
+
+        match key.as_str() {
+            "ncols" => ret.cols = Some(value.parse().map_err(HeaderParseError::InvalidInt)?),
+            "nrows" => ret.rows = Some(value.parse().map_err(HeaderParseError::InvalidInt)?),
+            "xllcorner" => ret.xllcorner = Some(parse_float(&value)?),
+            "yllcorner" => ret.yllcorner = Some(parse_float(&value)?),
+            "xllcenter" => ret.xllcenter = Some(parse_float(&value)?),
+            "yllcenter" => ret.yllcenter = Some(parse_float(&value)?),
+            "cellsize" => ret.cellsize = Some(parse_float(&value)?),
+            "dx" => ret.dx = Some(parse_float(&value)?),
+            "dy" => ret.dy = Some(parse_float(&value)?),
+            "nodata_value" => ret.nodata = Some(parse_float(&value)? as f32),
+            _ => return Err(HeaderParseError::InvalidKey(key)),
+        }
+
+        // Once we know we parsed correctly, we can increment the iterator for real
+        lines.next();
+        line_num += 1;
+    }
+
+    ret.try_into()
+}
+
+#[derive(Debug)]
+pub enum ElevationParseError {
+    HeaderParse(HeaderParseError),
+    Io(io::Error),
+    InvalidFloat(std::num::ParseFloatError),
+    InvalidDataSize {
+        expected_rows: usize,
+        expected_cols: usize,
+        actual_size: usize,
+    },
+}
+
+impl fmt::Display for ElevationParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use ElevationParseError::*;
+        match self {
+            HeaderParse(_) => write!(f, "Failed to parse header"),
+            Io(_) => write!(f, "Failed to read data"),
+            InvalidFloat(_) => write!(f, "Invalid float in data"),
+            InvalidDataSize {
+                expected_rows,
+                expected_cols,
+                actual_size,
+            } => {
+                write!(f, "Invalid data size. Expected {expected_rows}x{expected_cols}, got {actual_size}")
+            }
+        }
+    }
+}
+impl Error for ElevationParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        use ElevationParseError::*;
+        match self {
+            HeaderParse(s) => Some(s),
+            Io(s) => Some(s),
+            InvalidFloat(s) => Some(s),
+            InvalidDataSize { .. } => None,
+        }
+    }
+}
+
+/// Parses an Esri ASCII grid (https://en.wikipedia.org/wiki/Esri_grid) that is entirely in
+/// memory, e.g. a memory mapped file. Values are parsed in parallel, which is many times faster
+/// on large rasters
+pub fn parse_elevation_grid_bytes(bytes: &[u8]) -> Result<ElevationGrid, ElevationParseError> {
+    let (header_bytes, body) = bytes.split_at(header_len(bytes));
+    let header = parse_header(&mut header_bytes.lines().peekable())
+        .map_err(ElevationParseError::HeaderParse)?;
+
+    let target_chunk_size = (body.len() / (rayon::current_num_threads() * 4)).max(MIN_CHUNK_SIZE);
+    let chunks = split_chunks(body, target_chunk_size)
+        .into_par_iter()
+        .map(parse_values)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut data = Vec::with_capacity(header.rows * header.cols);
+    for chunk in chunks {
+        data.extend(chunk);
+    }
+
+    grid_from_parts(header, data)
+}
+
+/// Chunks smaller than this aren't worth handing to another thread
+const MIN_CHUNK_SIZE: usize = 1 << 20;
+
+/// Length of the header, which ends at the first line starting with a number
+fn header_len(bytes: &[u8]) -> usize {
+    let mut ret = 0;
+    for line in bytes.split_inclusive(|c| *c == b'\n') {
+        let first_token = line
+            .split(u8::is_ascii_whitespace)
+            .find(|t| !t.is_empty())
+            .and_then(|t| std::str::from_utf8(t).ok());
+        if first_token.is_some_and(|t| t.parse::<f32>().is_ok()) {
+            break;
+        }
+        ret += line.len();
+    }
+
+    ret
+}
+
+/// Splits roughly every `target_size` bytes, on whitespace so that no value is cut in half
+fn split_chunks(mut bytes: &[u8], target_size: usize) -> Vec<&[u8]> {
+    let mut ret = Vec::new();
+    while bytes.len() > target_size {
+        let split = match bytes[target_size..]
+            .iter()
+            .position(u8::is_ascii_whitespace)
+        {
+            Some(v) => target_size + v,
+            None => break,
+        };
+
+        let (chunk, rest) = bytes.split_at(split);
+        ret.push(chunk);
+        bytes = rest;
+    }
+    ret.push(bytes);
+
+    ret
+}
+
+fn parse_values(chunk: &[u8]) -> Result<Vec<f32>, ElevationParseError> {
+    // Invalid UTF-8 is an InvalidData io error, the same as BufRead::lines reports it
+    let text = std::str::from_utf8(chunk)
+        .map_err(|e| ElevationParseError::Io(io::Error::new(io::ErrorKind::InvalidData, e)))?;
+
+    text.split_whitespace()
+        .map(|v| v.parse::<f32>().map_err(ElevationParseError::InvalidFloat))
+        .collect()
+}
+
+fn grid_from_parts(header: Header, data: Vec<f32>) -> Result<ElevationGrid, ElevationParseError> {
+    if data.len() != header.rows * header.cols {
+        return Err(ElevationParseError::InvalidDataSize {
+            expected_rows: header.rows,
+            expected_cols: header.cols,
+            actual_size: data.len(),
+        });
+    }
+
+    let ret = ElevationGrid {
+        step_x: header.dx,
+        step_y: header.dy,
+        row_length: header.cols,
+        tl_corner: Point {
+            x: header.xllcorner,
+            y: header.yllcorner + header.dy * header.rows as f64,
+        },
+        nodata_val: header.nodata,
+        data,
+        interpolation: Interpolation::default(),
+        projection: Projection::default(),
+    };
+
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NODATA: f32 = -9999.0;
+
+    /// Grid of 1 degree cells with its top left corner at (0, rows). The value of each cell is
+    /// 10 * column + row, so interpolating between cells is exact
+    fn grid(cols: usize, rows: usize, interpolation: Interpolation) -> ElevationGrid {
+        let data = (0..rows)
+            .flat_map(|row| (0..cols).map(move |col| (10 * col + row) as f32))
+            .collect();
+
+        ElevationGrid {
+            step_x: 1.0,
+            step_y: 1.0,
+            row_length: cols,
+            tl_corner: Point {
+                x: 0.0,
+                y: rows as f64,
+            },
+            nodata_val: NODATA,
+            data,
+            interpolation,
+            projection: Projection::default(),
+        }
+    }
+
+    /// Lat/long of a position given in cell units, where cell centers are at integers
+    fn at(grid: &ElevationGrid, x: f32, y: f32) -> (f32, f32) {
+        (grid.num_rows() as f32 - y - 0.5, x + 0.5)
+    }
+
+    fn height(grid: &ElevationGrid, x: f32, y: f32) -> Option<f32> {
+        let (lat, long) = at(grid, x, y);
+        grid.height_at_lat_long(lat, long)
+    }
+
+    fn assert_close(actual: Option<f32>, expected: f32) {
+        let actual = actual.expect("expected a height");
+        assert!(
+            (actual - expected).abs() < 1e-4,
+            "expected {expected}, got {actual}"
+        );
+    }
+
+    const ALL: [Interpolation; 3] = [
+        Interpolation::Nearest,
+        Interpolation::Bilinear,
+        Interpolation::Bicubic,
+    ];
+
+    #[test]
+    fn cell_centers() {
+        for interpolation in ALL {
+            let grid = grid(4, 4, interpolation);
+            for row in 0..4 {
+                for col in 0..4 {
+                    let expected = (10 * col + row) as f32;
+                    assert_close(height(&grid, col as f32, row as f32), expected);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn mid_cell() {
+        let nearest = grid(4, 4, Interpolation::Nearest);
+        assert_close(height(&nearest, 1.25, 1.5), 12.0);
+        assert_close(height(&nearest, 1.75, 1.25), 21.0);
+
+        // Both are exact on a linear surface away from the edges
+        for interpolation in [Interpolation::Bilinear, Interpolation::Bicubic] {
+            let grid = grid(4, 4, interpolation);
+            assert_close(height(&grid, 1.25, 1.5), 14.0);
+            assert_close(height(&grid, 1.5, 1.75), 16.75);
+        }
+    }
+
+    #[test]
+    fn edges() {
+        for interpolation in ALL {
+            let grid = grid(4, 4, interpolation);
+            // Outer half of the corner cells
+            assert_close(height(&grid, -0.4, -0.4), 0.0);
+            assert_close(height(&grid, 3.4, 3.4), 33.0);
+            assert_close(height(&grid, -0.4, 3.4), 3.0);
+            assert_close(height(&grid, 3.4, -0.4), 30.0);
+        }
+
+        // Along an edge only the other axis is interpolated
+        let bilinear = grid(4, 4, Interpolation::Bilinear);
+        assert_close(height(&bilinear, 1.5, -0.4), 15.0);
+        assert_close(height(&bilinear, -0.4, 2.5), 2.5);
+    }
+
+    #[test]
+    fn nodata_neighbor() {
+        for nodata in [NODATA, f32::NAN] {
+            let mut nearest = grid(4, 4, Interpolation::Nearest);
+            nearest.data[4 + 1] = nodata;
+            assert_eq!(height(&nearest, 1.0, 1.0), None);
+            assert_eq!(height(&nearest, 1.4, 1.0), None);
+            assert_close(height(&nearest, 1.6, 1.0), 21.0);
+
+            for interpolation in [Interpolation::Bilinear, Interpolation::Bicubic] {
+                let mut grid = grid(4, 4, interpolation);
+                grid.data[4 + 1] = nodata;
+                assert_eq!(height(&grid, 1.0, 1.0), None);
+                // The missing cell is left out rather than blended in
+                assert_close(height(&grid, 1.5, 1.0), 21.0);
+                assert_close(height(&grid, 1.0, 1.5), 12.0);
+                // Cells further away are unaffected
+                assert_close(height(&grid, 2.5, 2.5), 27.5);
+            }
+        }
+    }
+
+    #[test]
+    fn outside_grid() {
+        for interpolation in ALL {
+            let grid = grid(4, 3, interpolation);
+            // Above and left of the top left corner
+            assert_eq!(grid.height_at_lat_long(3.01, 0.5), None);
+            assert_eq!(grid.height_at_lat_long(2.5, -0.01), None);
+            assert_eq!(grid.height_at_lat_long(3.5, -0.5), None);
+            // Far enough out that a wrapped index would land inside the grid
+            assert_eq!(grid.height_at_lat_long(7.5, 0.5), None);
+            assert_eq!(grid.height_at_lat_long(2.5, -3.5), None);
+            // Below and right of the bottom right corner
+            assert_eq!(grid.height_at_lat_long(-0.01, 0.5), None);
+            assert_eq!(grid.height_at_lat_long(0.5, 4.0), None);
+            assert_eq!(grid.height_at_lat_long(f32::NAN, 0.5), None);
+
+            // Just inside the corners
+            assert_close(grid.height_at_lat_long(2.99, 0.01), 0.0);
+            assert_close(grid.height_at_lat_long(0.01, 3.99), 32.0);
+        }
+    }
+
+    fn header(text: &str) -> Result<Header, HeaderParseError> {
+        parse_header(&mut text.as_bytes().lines().peekable())
+    }
+
+    #[test]
+    fn header_corner_and_center() {
+        let corner =
+            header("ncols 4\nnrows 3\nxllcorner 10\nyllcorner 20\ncellsize 2\n1 2 3 4\n").unwrap();
+        assert_eq!((corner.cols, corner.rows), (4, 3));
+        assert_eq!((corner.xllcorner, corner.yllcorner), (10.0, 20.0));
+
+        // Centers are half a cell in from the corner
+        let center = header("ncols 4\nnrows 3\nxllcenter 10\nyllcenter 20\ncellsize 2\n").unwrap();
+        assert_eq!((center.xllcorner, center.yllcorner), (9.0, 19.0));
+
+        let center =
+            header("ncols 4\nnrows 3\nxllcenter 10\nyllcenter 20\ndx 2\ndy 0.5\n").unwrap();
+        assert_eq!((center.xllcorner, center.yllcorner), (9.0, 19.75));
+
+        // Keys are case insensitive and the two can be mixed
+        let mixed = header("NCOLS 4\nNROWS 3\nXLLCORNER 10\nYLLCENTER 20\nCELLSIZE 2\n").unwrap();
+        assert_eq!((mixed.xllcorner, mixed.yllcorner), (10.0, 19.0));
+    }
+
+    #[test]
+    fn header_cellsize_and_steps() {
+        let cellsize =
+            header("ncols 1\nnrows 1\nxllcorner 0\nyllcorner 0\ncellsize 0.25\n").unwrap();
+        assert_eq!((cellsize.dx, cellsize.dy), (0.25, 0.25));
+
+        let steps =
+            header("ncols 1\nnrows 1\nxllcorner 0\nyllcorner 0\ndx 0.25\ndy 0.5\n").unwrap();
+        assert_eq!((steps.dx, steps.dy), (0.25, 0.5));
+    }
+
+    #[test]
+    fn header_nodata() {
+        let missing = header("ncols 1\nnrows 1\nxllcorner 0\nyllcorner 0\ncellsize 1\n").unwrap();
+        assert_eq!(missing.nodata, DEFAULT_NODATA);
+
+        let given =
+            header("ncols 1\nnrows 1\nxllcorner 0\nyllcorner 0\ncellsize 1\nNODATA_value -1\n")
+                .unwrap();
+        assert_eq!(given.nodata, -1.0);
+    }
+
+    #[test]
+    fn header_conflicting_fields() {
+        let base = "ncols 1\nnrows 1\n";
+        let cases = [
+            (
+                "xllcorner 0\nyllcorner 0\ncellsize 1\ndx 1\ndy 1\n",
+                ("cellsize", "dx"),
+            ),
+            (
+                "xllcorner 0\nyllcorner 0\ncellsize 1\ndx 1\n",
+                ("cellsize", "dx"),
+            ),
+            (
+                "xllcorner 0\nyllcorner 0\ncellsize 1\ndy 1\n",
+                ("cellsize", "dy"),
+            ),
+            (
+                "xllcorner 0\nxllcenter 0\nyllcorner 0\ncellsize 1\n",
+                ("xllcorner", "xllcenter"),
+            ),
+            (
+                "xllcorner 0\nyllcorner 0\nyllcenter 0\ncellsize 1\n",
+                ("yllcorner", "yllcenter"),
+            ),
+        ];
+
+        for (fields, expected) in cases {
+            match header(&format!("{base}{fields}")) {
+                Err(HeaderParseError::ConflictingFields(a, b)) => assert_eq!((a, b), expected),
+                other => panic!("expected {expected:?} to conflict, got {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn header_missing_fields() {
+        let cases = [
+            ("nrows 1\nxllcorner 0\nyllcorner 0\ncellsize 1\n", "ncols"),
+            ("ncols 1\nxllcorner 0\nyllcorner 0\ncellsize 1\n", "nrows"),
+            ("ncols 1\nnrows 1\nyllcorner 0\ncellsize 1\n", "xllcorner"),
+            ("ncols 1\nnrows 1\nxllcorner 0\ncellsize 1\n", "yllcorner"),
+            ("ncols 1\nnrows 1\nxllcorner 0\nyllcorner 0\n", "cellsize"),
+            ("ncols 1\nnrows 1\nxllcorner 0\nyllcorner 0\ndx 1\n", "dy"),
+            ("ncols 1\nnrows 1\nxllcorner 0\nyllcorner 0\ndy 1\n", "dx"),
+        ];
+
+        for (text, expected) in cases {
+            match header(text) {
+                Err(HeaderParseError::MissingFields(field)) => assert_eq!(field, expected),
+                other => panic!("expected {expected} to be missing, got {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn header_invalid_lines() {
+        assert!(matches!(
+            header("ncols 1\nnrows 1\nfoo 1\n"),
+            Err(HeaderParseError::InvalidKey(k)) if k == "foo"
+        ));
+        assert!(matches!(
+            header("ncols 1\nnrows\n"),
+            Err(HeaderParseError::MissingValue(1))
+        ));
+        assert!(matches!(
+            header("ncols 1 2\n"),
+            Err(HeaderParseError::ExtraHeaderValue(0))
+        ));
+        assert!(matches!(
+            header("ncols x\n"),
+            Err(HeaderParseError::InvalidInt(_))
+        ));
+    }
+
+    const GRID: &str = "ncols 3\nnrows 2\nxllcorner 1.5\nyllcorner -2\ncellsize 0.5\n\
+        NODATA_value -1\n1 2.5 -1\n4e1 -5 6\n";
+
+    fn assert_parses(bytes: &[u8]) {
+        let grid = parse_elevation_grid_bytes(bytes).unwrap();
+        assert_eq!((grid.step_x, grid.step_y), (0.5, 0.5));
+        assert_eq!(grid.row_length, 3);
+        assert_eq!((grid.tl_corner.x, grid.tl_corner.y), (1.5, -1.0));
+        assert_eq!(grid.nodata_val, -1.0);
+        assert_eq!(grid.data, [1.0, 2.5, -1.0, 40.0, -5.0, 6.0]);
+    }
+
+    #[test]
+    fn parse_grid() {
+        assert_parses(GRID.as_bytes());
+        assert_parses(GRID.replace('\n', "\r\n").as_bytes());
+        assert_parses(GRID.replace('\n', " \t\n").as_bytes());
+        assert_parses(GRID.trim_end().as_bytes());
+        assert_parses(
+            GRID.replace("1 2.5 -1\n4e1", "1\n\n2.5   -1 4e1")
+                .as_bytes(),
+        );
+    }
+
+    #[test]
+    fn parse_grid_gz() {
+        use flate2::{read::MultiGzDecoder, write::GzEncoder, Compression};
+        use std::io::{Read, Write};
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(GRID.as_bytes()).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        // Read the same way as rasters.rs reads .asc.gz files
+        let mut bytes = Vec::new();
+        MultiGzDecoder::new(&compressed[..])
+            .read_to_end(&mut bytes)
+            .unwrap();
+        assert_parses(&bytes);
+    }
+
+    #[test]
+    fn parse_grid_wrong_size() {
+        let short = GRID.replace(" 6\n", "\n");
+        assert!(matches!(
+            parse_elevation_grid_bytes(short.as_bytes()),
+            Err(ElevationParseError::InvalidDataSize {
+                expected_rows: 2,
+                expected_cols: 3,
+                actual_size: 5
+            })
+        ));
+    }
+
+    /// The simplest possible parse, one line at a time, to check the parallel parser against
+    fn parse_line_by_line(bytes: &[u8]) -> ElevationGrid {
+        let mut lines = bytes.lines().peekable();
+        let header = parse_header(&mut lines).unwrap();
+
+        let mut data = Vec::new();
+        for line in lines {
+            for value in line.unwrap().split_whitespace() {
+                data.push(value.parse::<f32>().unwrap());
+            }
+        }
+
+        grid_from_parts(header, data).unwrap()
+    }
+
+    fn assert_matches_line_by_line(text: &str) {
+        let expected = parse_line_by_line(text.as_bytes());
+        let actual = parse_elevation_grid_bytes(text.as_bytes()).unwrap();
+
+        assert_eq!(
+            (actual.step_x, actual.step_y),
+            (expected.step_x, expected.step_y)
+        );
+        assert_eq!(actual.row_length, expected.row_length);
+        assert_eq!(
+            (actual.tl_corner.x, actual.tl_corner.y),
+            (expected.tl_corner.x, expected.tl_corner.y)
+        );
+        assert_eq!(actual.nodata_val, expected.nodata_val);
+        assert_eq!(actual.data, expected.data);
+    }
+
+    #[test]
+    fn parsers_agree() {
+        // Big enough that the parallel parser splits it into several chunks
+        let (cols, rows) = (500, 500);
+        let mut text = format!(
+            "ncols {cols}\nnrows {rows}\nxllcorner -3.5\nyllcorner 51\ncellsize 0.25\n\
+            NODATA_value -9999\n"
+        );
+        for row in 0..rows {
+            let values: Vec<String> = (0..cols)
+                .map(|col| match (row * cols + col) % 97 {
+                    0 => "-9999".to_string(),
+                    v => format!("{}", (v as f32 - 40.0) * 1.37),
+                })
+                .collect();
+            text += &values.join(" ");
+            text += "\n";
+        }
+        assert!(split_chunks(text.as_bytes(), MIN_CHUNK_SIZE).len() > 1);
+
+        for text in [
+            text.clone(),
+            text.replace('\n', "\r\n"),
+            text.replace('\n', " \t\n"),
+            text.trim_end().to_string(),
+            text.replace('\n', " \t\r\n").trim_end().to_string(),
+            GRID.to_string(),
+            GRID.replace("1 2.5 -1\n4e1", "1\n\n2.5   -1 4e1"),
+        ] {
+            assert_matches_line_by_line(&text);
+        }
+    }
+
+    #[test]
+    fn chunks_split_on_whitespace() {
+        let body = "1 22 333\r\n4444 5 66\n777 8";
+        for target_size in 1..body.len() + 2 {
+            let values = split_chunks(body.as_bytes(), target_size)
+                .into_iter()
+                .map(|c| parse_values(c).unwrap())
+                .collect::<Vec<_>>()
+                .concat();
+            assert_eq!(values, [1.0, 22.0, 333.0, 4444.0, 5.0, 66.0, 777.0, 8.0]);
+        }
+    }
+
+    #[test]
+    fn projected_grid() {
+        // 100m cells in UTM zone 10N, each holding 100 * row + column
+        let mut text =
+            "ncols 20\nnrows 20\nxllcorner 492000\nyllcorner 5454000\ncellsize 100\n".to_string();
+        for row in 0..20 {
+            let values: Vec<String> = (0..20).map(|col| (100 * row + col).to_string()).collect();
+            text += &values.join(" ");
+            text += "\n";
+        }
+
+        let mut grid = parse_elevation_grid_bytes(text.as_bytes()).unwrap();
+        grid.set_projection(projection::parse_prj(r#"PROJCS["NAD_1983_UTM_Zone_10N",GEOGCS["GCS_North_American_1983",DATUM["D_North_American_1983",SPHEROID["GRS_1980",6378137.0,298.257222101]],PRIMEM["Greenwich",0.0],UNIT["Degree",0.0174532925199433]],PROJECTION["Transverse_Mercator"],PARAMETER["False_Easting",500000.0],PARAMETER["False_Northing",0.0],PARAMETER["Central_Meridian",-123.0],PARAMETER["Scale_Factor",0.9996],PARAMETER["Latitude_Of_Origin",0.0],UNIT["Meter",1.0]]"#).unwrap());
+
+        // Projects to (492722.425, 5455252.511), 7 cells in from the left and 7 down from the top
+        assert_eq!(grid.height_at_lat_long(49.25, -123.1), Some(707.0));
+        assert_eq!(grid.height_at_lat_long(49.25, -123.0), None);
+        assert!((grid.resolution() - 100.0 / 111_320.0).abs() < 1e-7);
+    }
+
+    #[test]
+    fn finest_source_wins() {
+        let coarse = grid(4, 4, Interpolation::Nearest);
+        // Covers the top left coarse cell with 4 cells, one of them missing
+        let fine = ElevationGrid {
+            step_x: 0.5,
+            step_y: 0.5,
+            row_length: 2,
+            tl_corner: Point { x: 0.0, y: 4.0 },
+            nodata_val: NODATA,
+            data: vec![100.0, 101.0, 102.0, NODATA],
+            interpolation: Interpolation::Nearest,
+            projection: Projection::default(),
+        };
+
+        let data = ElevationData::new(vec![Box::new(coarse), Box::new(fine)]);
+        assert_eq!(data.height_at_lat_long(3.75, 0.25), Some(100.0));
+        assert_eq!(data.height_at_lat_long(3.25, 0.25), Some(102.0));
+        // Nodata in the fine grid falls through to the coarse one
+        assert_eq!(data.height_at_lat_long(3.25, 0.75), Some(0.0));
+        // As does anything outside of it
+        assert_eq!(data.height_at_lat_long(2.5, 1.5), Some(11.0));
+        assert_eq!(data.height_at_lat_long(4.5, 0.5), None);
+    }
+}
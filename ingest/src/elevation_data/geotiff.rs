@@ -0,0 +1,266 @@
+use super::{ElevationGrid, Interpolation, Point, Projection};
+use std::{
+    error::Error,
+    fmt,
+    io::{Read, Seek},
+};
+use tiff::{
+    decoder::{Decoder, DecodingResult, Limits},
+    tags::Tag,
+    ColorType, TiffError,
+};
+
+/// GTModelTypeGeoKey, says whether the raster is in projected or geographic coordinates
+const MODEL_TYPE_GEO_KEY: u16 = 1024;
+const MODEL_TYPE_PROJECTED: u16 = 1;
+const MODEL_TYPE_GEOGRAPHIC: u16 = 2;
+/// GTRasterTypeGeoKey, says whether the tie point refers to a pixel's corner or its center
+const RASTER_TYPE_GEO_KEY: u16 = 1025;
+const RASTER_PIXEL_IS_POINT: u16 = 2;
+/// ProjectedCSTypeGeoKey, EPSG code of the projected coordinate system
+const PROJECTED_CS_TYPE_GEO_KEY: u16 = 3072;
+const USER_DEFINED: u16 = 32767;
+
+#[derive(Debug)]
+pub enum GeoTiffParseError {
+    Tiff(TiffError),
+    NotSingleBand(ColorType),
+    MissingGeoreference,
+    UnsupportedModelType(u16),
+    /// EPSG code of a projected system we don't know, or [`USER_DEFINED`]
+    UnsupportedProjection(u16),
+    InvalidDataSize {
+        expected: usize,
+        actual: usize,
+    },
+}
+
+impl fmt::Display for GeoTiffParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use GeoTiffParseError::*;
+        match self {
+            Tiff(_) => write!(f, "Failed to decode tiff"),
+            NotSingleBand(color_type) => {
+                write!(f, "Expected a single band DEM, got {color_type:?}")
+            }
+            MissingGeoreference => write!(f, "Missing ModelPixelScale or ModelTiepoint tag"),
+            UnsupportedModelType(model_type) => {
+                write!(f, "Unsupported GeoTIFF model type {model_type}")
+            }
+            UnsupportedProjection(USER_DEFINED) => {
+                write!(f, "User defined projection, a .prj describing it is needed")
+            }
+            UnsupportedProjection(code) => {
+                write!(
+                    f,
+                    "Unsupported projection EPSG:{code}, a .prj describing it is needed"
+                )
+            }
+            InvalidDataSize { expected, actual } => {
+                write!(f, "Invalid data size. Expected {expected}, got {actual}")
+            }
+        }
+    }
+}
+
+impl Error for GeoTiffParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        use GeoTiffParseError::*;
+        match self {
+            Tiff(s) => Some(s),
+            NotSingleBand(_)
+            | MissingGeoreference
+            | UnsupportedModelType(_)
+            | UnsupportedProjection(_)
+            | InvalidDataSize { .. } => None,
+        }
+    }
+}
+
+fn decoding_result_to_f32(result: DecodingResult) -> Vec<f32> {
+    use DecodingResult::*;
+    match result {
+        U8(v) => v.into_iter().map(|x| x as f32).collect(),
+        U16(v) => v.into_iter().map(|x| x as f32).collect(),
+        U32(v) => v.into_iter().map(|x| x as f32).collect(),
+        U64(v) => v.into_iter().map(|x| x as f32).collect(),
+        F32(v) => v,
+        F64(v) => v.into_iter().map(|x| x as f32).collect(),
+        I8(v) => v.into_iter().map(|x| x as f32).collect(),
+        I16(v) => v.into_iter().map(|x| x as f32).collect(),
+        I32(v) => v.into_iter().map(|x| x as f32).collect(),
+        I64(v) => v.into_iter().map(|x| x as f32).collect(),
+    }
+}
+
+/// (key, value) pairs of the GeoKeys stored directly in the directory. Keys whose values live in
+/// other tags are doubles or strings, none of which we need
+fn geo_keys<R>(decoder: &mut Decoder<R>) -> Result<Vec<(u16, u16)>, TiffError>
+where
+    R: Read + Seek,
+{
+    let directory = match decoder.find_tag(Tag::GeoKeyDirectoryTag)? {
+        Some(v) => v.into_u16_vec()?,
+        None => return Ok(Vec::new()),
+    };
+
+    // Header of 4 shorts followed by (key, location, count, value) entries
+    let keys = directory
+        .get(4..)
+        .unwrap_or_default()
+        .chunks_exact(4)
+        .filter(|entry| entry[1] == 0)
+        .map(|entry| (entry[0], entry[3]))
+        .collect();
+
+    Ok(keys)
+}
+
+fn geo_key(keys: &[(u16, u16)], key: u16) -> Option<u16> {
+    keys.iter().find(|(k, _)| *k == key).map(|(_, v)| *v)
+}
+
+/// Rasters without a model type are assumed to be geographic, same as rasters without a .prj
+fn projection_from_geo_keys(keys: &[(u16, u16)]) -> Result<Projection, GeoTiffParseError> {
+    match geo_key(keys, MODEL_TYPE_GEO_KEY) {
+        None | Some(MODEL_TYPE_GEOGRAPHIC) => Ok(Projection::default()),
+        Some(MODEL_TYPE_PROJECTED) => {
+            let code = geo_key(keys, PROJECTED_CS_TYPE_GEO_KEY).unwrap_or(USER_DEFINED);
+            Projection::from_epsg(code).ok_or(GeoTiffParseError::UnsupportedProjection(code))
+        }
+        Some(model_type) => Err(GeoTiffParseError::UnsupportedModelType(model_type)),
+    }
+}
+
+/// Single band GeoTIFF DEM. `projection` comes from a .prj next to the file and takes precedence
+/// over the GeoKeys, which are only understood for geographic and common UTM systems
+pub fn parse_geotiff<R>(
+    reader: R,
+    projection: Option<Projection>,
+) -> Result<ElevationGrid, GeoTiffParseError>
+where
+    R: Read + Seek,
+{
+    use GeoTiffParseError as E;
+
+    // DEMs routinely exceed the default decoding limits
+    let mut decoder = Decoder::new(reader)
+        .map_err(E::Tiff)?
+        .with_limits(Limits::unlimited());
+
+    match decoder.colortype().map_err(E::Tiff)? {
+        ColorType::Gray(_) => (),
+        color_type => return Err(E::NotSingleBand(color_type)),
+    }
+
+    let (cols, rows) = decoder.dimensions().map_err(E::Tiff)?;
+    let (cols, rows) = (cols as usize, rows as usize);
+
+    let scale = decoder
+        .find_tag(Tag::ModelPixelScaleTag)
+        .map_err(E::Tiff)?
+        .ok_or(E::MissingGeoreference)?
+        .into_f64_vec()
+        .map_err(E::Tiff)?;
+    let tie_point = decoder
+        .find_tag(Tag::ModelTiepointTag)
+        .map_err(E::Tiff)?
+        .ok_or(E::MissingGeoreference)?
+        .into_f64_vec()
+        .map_err(E::Tiff)?;
+    if scale.len() < 2 || tie_point.len() < 6 {
+        return Err(E::MissingGeoreference);
+    }
+
+    let (scale_x, scale_y) = (scale[0], scale[1]);
+
+    let geo_keys = geo_keys(&mut decoder).map_err(E::Tiff)?;
+    let projection = match projection {
+        Some(v) => v,
+        None => projection_from_geo_keys(&geo_keys)?,
+    };
+
+    // Tie point maps raster position (i, j) to model position (x, y)
+    let mut left = tie_point[3] - tie_point[0] * scale_x;
+    let mut top = tie_point[4] + tie_point[1] * scale_y;
+    if geo_key(&geo_keys, RASTER_TYPE_GEO_KEY) == Some(RASTER_PIXEL_IS_POINT) {
+        left -= scale_x / 2.0;
+        top += scale_y / 2.0;
+    }
+
+    // Without a nodata value only NaN samples are treated as missing
+    let nodata_val = match decoder.find_tag(Tag::GdalNodata).map_err(E::Tiff)? {
+        Some(v) => v
+            .into_string()
+            .ok()
+            .and_then(|s| s.trim_matches(char::from(0)).trim().parse().ok())
+            .unwrap_or(f32::NAN),
+        None => f32::NAN,
+    };
+
+    let data = decoding_result_to_f32(decoder.read_image().map_err(E::Tiff)?);
+    if data.len() != rows * cols {
+        return Err(E::InvalidDataSize {
+            expected: rows * cols,
+            actual: data.len(),
+        });
+    }
+
+    Ok(ElevationGrid {
+        step_x: scale_x,
+        step_y: scale_y,
+        row_length: cols,
+        tl_corner: Point { x: left, y: top },
+        nodata_val,
+        data,
+        interpolation: Interpolation::default(),
+        projection,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn projection_from_keys() {
+        let (x, _) = projection_from_geo_keys(&[])
+            .unwrap()
+            .forward(49.25, -123.1);
+        assert_eq!(x, -123.1);
+
+        let geographic = [(MODEL_TYPE_GEO_KEY, MODEL_TYPE_GEOGRAPHIC), (2048, 4326)];
+        let (x, _) = projection_from_geo_keys(&geographic)
+            .unwrap()
+            .forward(49.25, -123.1);
+        assert_eq!(x, -123.1);
+
+        let utm = [
+            (MODEL_TYPE_GEO_KEY, MODEL_TYPE_PROJECTED),
+            (RASTER_TYPE_GEO_KEY, 1),
+            (PROJECTED_CS_TYPE_GEO_KEY, 26910),
+        ];
+        let (x, y) = projection_from_geo_keys(&utm)
+            .unwrap()
+            .forward(49.25, -123.1);
+        assert!((x - 492722.425).abs() < 0.03 && (y - 5455252.511).abs() < 0.03);
+
+        // Projected rasters must not be read as lat/long
+        let unknown = [
+            (MODEL_TYPE_GEO_KEY, MODEL_TYPE_PROJECTED),
+            (PROJECTED_CS_TYPE_GEO_KEY, 3857),
+        ];
+        assert!(matches!(
+            projection_from_geo_keys(&unknown),
+            Err(GeoTiffParseError::UnsupportedProjection(3857))
+        ));
+        assert!(matches!(
+            projection_from_geo_keys(&[(MODEL_TYPE_GEO_KEY, MODEL_TYPE_PROJECTED)]),
+            Err(GeoTiffParseError::UnsupportedProjection(USER_DEFINED))
+        ));
+        assert!(matches!(
+            projection_from_geo_keys(&[(MODEL_TYPE_GEO_KEY, 3)]),
+            Err(GeoTiffParseError::UnsupportedModelType(3))
+        ));
+    }
+}
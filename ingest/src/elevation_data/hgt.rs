@@ -0,0 +1,107 @@
+use super::{ElevationGrid, Interpolation, Point, Projection};
+use std::{
+    error::Error,
+    fmt,
+    io::{self, Read},
+};
+
+/// Marks voids in the SRTM data
+const HGT_NODATA: i16 = -32768;
+
+#[derive(Debug)]
+pub enum HgtParseError {
+    Io(io::Error),
+    InvalidName(String),
+    InvalidSize(usize),
+}
+
+impl fmt::Display for HgtParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use HgtParseError::*;
+        match self {
+            Io(_) => write!(f, "Failed to read hgt data"),
+            InvalidName(name) => write!(
+                f,
+                "Tile name {name} does not describe a location, expected something like N49W123"
+            ),
+            InvalidSize(size) => write!(
+                f,
+                "Unexpected hgt size {size}, expected a 1 or 3 arc-second tile"
+            ),
+        }
+    }
+}
+
+impl Error for HgtParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        use HgtParseError::*;
+        match self {
+            Io(s) => Some(s),
+            InvalidName(_) | InvalidSize(_) => None,
+        }
+    }
+}
+
+/// Tiles carry no header, their south west corner is encoded in the file name, e.g. N49W123
+fn parse_tile_name(name: &str) -> Option<(i32, i32)> {
+    let name = name.to_uppercase();
+    let lat_sign = match name.get(0..1)? {
+        "N" => 1,
+        "S" => -1,
+        _ => return None,
+    };
+    let lat: i32 = name.get(1..3)?.parse().ok()?;
+
+    let long_sign = match name.get(3..4)? {
+        "E" => 1,
+        "W" => -1,
+        _ => return None,
+    };
+    let long: i32 = name.get(4..7)?.parse().ok()?;
+
+    Some((lat_sign * lat, long_sign * long))
+}
+
+/// SRTM height tile https://www.usgs.gov/centers/eros/science/usgs-eros-archive-digital-elevation-shuttle-radar-topography-mission-srtm
+///
+/// `tile_name` is the file name without extension
+pub fn parse_hgt<T>(tile_name: &str, mut buf: T) -> Result<ElevationGrid, HgtParseError>
+where
+    T: Read,
+{
+    let (lat, long) =
+        parse_tile_name(tile_name).ok_or_else(|| HgtParseError::InvalidName(tile_name.into()))?;
+
+    let mut bytes = Vec::new();
+    buf.read_to_end(&mut bytes).map_err(HgtParseError::Io)?;
+
+    // Tiles are square, 1201 samples a side for 3 arc-second data, 3601 for 1 arc-second
+    let samples = match bytes.len() {
+        2884802 => 1201,
+        25934402 => 3601,
+        size => return Err(HgtParseError::InvalidSize(size)),
+    };
+
+    let data = bytes
+        .chunks_exact(2)
+        .map(|b| i16::from_be_bytes([b[0], b[1]]) as f32)
+        .collect();
+
+    // Samples sit on whole arc-seconds, starting exactly on the tile's north west corner. Treat
+    // each as the center of a cell
+    let step = 1.0 / (samples - 1) as f64;
+
+    Ok(ElevationGrid {
+        step_x: step,
+        step_y: step,
+        row_length: samples,
+        tl_corner: Point {
+            x: long as f64 - step / 2.0,
+            y: (lat + 1) as f64 + step / 2.0,
+        },
+        nodata_val: HGT_NODATA as f32,
+        data,
+        interpolation: Interpolation::default(),
+        projection: Projection::default(),
+    })
+}
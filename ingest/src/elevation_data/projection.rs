@@ -0,0 +1,615 @@
+use std::{error::Error, f64::consts::FRAC_PI_4, fmt};
+
+/// Roughly how many meters a degree of latitude spans, used to compare resolutions across CRSs
+const METERS_PER_DEGREE: f64 = 111_320.0;
+
+#[derive(Debug)]
+pub enum PrjParseError {
+    InvalidWkt(usize),
+    MissingNode(&'static str),
+    MissingParameter(String),
+    UnsupportedProjection(String),
+}
+
+impl fmt::Display for PrjParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use PrjParseError::*;
+        match self {
+            InvalidWkt(pos) => write!(f, "Invalid WKT at offset {pos}"),
+            MissingNode(name) => write!(f, "Projection is missing {name}"),
+            MissingParameter(name) => write!(f, "Projection is missing parameter {name}"),
+            UnsupportedProjection(name) => write!(f, "Unsupported projection {name}"),
+        }
+    }
+}
+
+impl Error for PrjParseError {}
+
+enum WktValue {
+    Number(f64),
+    Text(String),
+    Node(WktNode),
+}
+
+/// A KEYWORD[value, value, ...] element of a WKT string
+struct WktNode {
+    name: String,
+    values: Vec<WktValue>,
+}
+
+impl WktNode {
+    fn child(&self, name: &str) -> Option<&WktNode> {
+        self.values.iter().find_map(|v| match v {
+            WktValue::Node(n) if n.name.eq_ignore_ascii_case(name) => Some(n),
+            _ => None,
+        })
+    }
+
+    fn children<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a WktNode> + 'a {
+        self.values.iter().filter_map(move |v| match v {
+            WktValue::Node(n) if n.name.eq_ignore_ascii_case(name) => Some(n),
+            _ => None,
+        })
+    }
+
+    fn text(&self, idx: usize) -> Option<&str> {
+        match self.values.get(idx)? {
+            WktValue::Text(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn number(&self, idx: usize) -> Option<f64> {
+        match self.values.get(idx)? {
+            WktValue::Number(v) => Some(*v),
+            _ => None,
+        }
+    }
+}
+
+struct WktParser<'a> {
+    input: &'a [u8],
+    pos: usize,
+}
+
+impl WktParser<'_> {
+    fn skip_whitespace(&mut self) {
+        while self
+            .input
+            .get(self.pos)
+            .is_some_and(u8::is_ascii_whitespace)
+        {
+            self.pos += 1;
+        }
+    }
+
+    fn err(&self) -> PrjParseError {
+        PrjParseError::InvalidWkt(self.pos)
+    }
+
+    fn take_while(&mut self, f: impl Fn(u8) -> bool) -> &str {
+        let start = self.pos;
+        while self.input.get(self.pos).is_some_and(|c| f(*c)) {
+            self.pos += 1;
+        }
+        // Only ever split on ascii characters, so this stays valid utf8
+        std::str::from_utf8(&self.input[start..self.pos]).unwrap_or_default()
+    }
+
+    fn value(&mut self) -> Result<WktValue, PrjParseError> {
+        self.skip_whitespace();
+        match self.input.get(self.pos) {
+            Some(b'"') => {
+                self.pos += 1;
+                let text = self.take_while(|c| c != b'"').to_string();
+                if self.input.get(self.pos) != Some(&b'"') {
+                    return Err(self.err());
+                }
+                self.pos += 1;
+                Ok(WktValue::Text(text))
+            }
+            Some(c) if c.is_ascii_digit() || *c == b'-' || *c == b'+' || *c == b'.' => {
+                let number = self.take_while(|c| {
+                    c.is_ascii_digit() || matches!(c, b'-' | b'+' | b'.' | b'e' | b'E')
+                });
+                number.parse().map(WktValue::Number).map_err(|_| self.err())
+            }
+            Some(_) => self.node(),
+            None => Err(self.err()),
+        }
+    }
+
+    fn node(&mut self) -> Result<WktValue, PrjParseError> {
+        self.skip_whitespace();
+        let name = self
+            .take_while(|c| c.is_ascii_alphanumeric() || c == b'_')
+            .to_string();
+        if name.is_empty() {
+            return Err(self.err());
+        }
+
+        self.skip_whitespace();
+        // Bare keywords such as the EAST in AXIS["X",EAST]
+        if !matches!(self.input.get(self.pos), Some(b'[') | Some(b'(')) {
+            return Ok(WktValue::Text(name));
+        }
+        self.pos += 1;
+
+        let mut values = Vec::new();
+        loop {
+            values.push(self.value()?);
+            self.skip_whitespace();
+            match self.input.get(self.pos) {
+                Some(b',') => self.pos += 1,
+                Some(b']') | Some(b')') => {
+                    self.pos += 1;
+                    break;
+                }
+                _ => return Err(self.err()),
+            }
+        }
+
+        Ok(WktValue::Node(WktNode { name, values }))
+    }
+}
+
+fn parse_wkt(wkt: &str) -> Result<WktNode, PrjParseError> {
+    let mut parser = WktParser {
+        input: wkt.as_bytes(),
+        pos: 0,
+    };
+
+    match parser.node()? {
+        WktValue::Node(n) => Ok(n),
+        _ => Err(parser.err()),
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Ellipsoid {
+    a: f64,
+    /// First eccentricity squared
+    e2: f64,
+}
+
+impl Ellipsoid {
+    const WGS84: Ellipsoid = Ellipsoid {
+        a: 6378137.0,
+        e2: 0.0066943799901413165,
+    };
+
+    const GRS80: Ellipsoid = Ellipsoid {
+        a: 6378137.0,
+        e2: 0.006694380022900787,
+    };
+
+    fn e(&self) -> f64 {
+        self.e2.sqrt()
+    }
+
+    /// Distance along the meridian from the equator to latitude phi
+    fn meridian_arc(&self, phi: f64) -> f64 {
+        let e2 = self.e2;
+        let e4 = e2 * e2;
+        let e6 = e4 * e2;
+        self.a
+            * ((1.0 - e2 / 4.0 - 3.0 * e4 / 64.0 - 5.0 * e6 / 256.0) * phi
+                - (3.0 * e2 / 8.0 + 3.0 * e4 / 32.0 + 45.0 * e6 / 1024.0) * (2.0 * phi).sin()
+                + (15.0 * e4 / 256.0 + 45.0 * e6 / 1024.0) * (4.0 * phi).sin()
+                - (35.0 * e6 / 3072.0) * (6.0 * phi).sin())
+    }
+
+    fn m(&self, phi: f64) -> f64 {
+        phi.cos() / (1.0 - self.e2 * phi.sin().powi(2)).sqrt()
+    }
+
+    fn t(&self, phi: f64) -> f64 {
+        let e_sin = self.e() * phi.sin();
+        (FRAC_PI_4 - phi / 2.0).tan() / ((1.0 - e_sin) / (1.0 + e_sin)).powf(self.e() / 2.0)
+    }
+
+    fn q(&self, phi: f64) -> f64 {
+        let e = self.e();
+        let sin = phi.sin();
+        (1.0 - self.e2)
+            * (sin / (1.0 - self.e2 * sin * sin)
+                - (1.0 / (2.0 * e)) * ((1.0 - e * sin) / (1.0 + e * sin)).ln())
+    }
+}
+
+/// Conic projections reduce to the same forward equations once their constants are known
+#[derive(Debug, Clone, Copy)]
+struct Conic {
+    n: f64,
+    /// Constant scaling the cone, F for Lambert, C for Albers
+    c: f64,
+    rho0: f64,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Kind {
+    Geographic,
+    TransverseMercator { k0: f64, m0: f64 },
+    LambertConformalConic(Conic),
+    AlbersEqualArea(Conic),
+}
+
+/// Maps WGS84 coordinates into a raster's coordinate reference system. Datum shifts are not
+/// applied, the difference between WGS84 and the datums DEMs are commonly published in is well
+/// below the size of a cell
+#[derive(Debug, Clone, Copy)]
+pub struct Projection {
+    kind: Kind,
+    ellipsoid: Ellipsoid,
+    /// Radians
+    central_meridian: f64,
+    /// Projection units
+    false_easting: f64,
+    false_northing: f64,
+    /// Meters per unit of the projected coordinates
+    unit: f64,
+}
+
+impl Default for Projection {
+    fn default() -> Projection {
+        Projection {
+            kind: Kind::Geographic,
+            ellipsoid: Ellipsoid::WGS84,
+            central_meridian: 0.0,
+            false_easting: 0.0,
+            false_northing: 0.0,
+            unit: 1.0,
+        }
+    }
+}
+
+impl Projection {
+    /// Projection for an EPSG code, as found in GeoTIFF GeoKeys. Only the UTM zones DEMs are
+    /// commonly published in are known, anything else needs a .prj
+    pub fn from_epsg(code: u16) -> Option<Projection> {
+        let (ellipsoid, zone, south) = match code {
+            // WGS 84
+            32601..=32660 => (Ellipsoid::WGS84, code - 32600, false),
+            32701..=32760 => (Ellipsoid::WGS84, code - 32700, true),
+            // NAD83
+            26901..=26923 => (Ellipsoid::GRS80, code - 26900, false),
+            // ETRS89
+            25828..=25838 => (Ellipsoid::GRS80, code - 25800, false),
+            _ => return None,
+        };
+
+        Some(Projection {
+            kind: Kind::TransverseMercator {
+                k0: 0.9996,
+                m0: 0.0,
+            },
+            ellipsoid,
+            central_meridian: (zone as f64 * 6.0 - 183.0).to_radians(),
+            false_easting: 500_000.0,
+            false_northing: if south { 10_000_000.0 } else { 0.0 },
+            unit: 1.0,
+        })
+    }
+
+    /// Converts a distance in the projection's units to approximate degrees
+    pub fn distance_in_degrees(&self, v: f32) -> f32 {
+        match self.kind {
+            Kind::Geographic => v,
+            _ => (v as f64 * self.unit / METERS_PER_DEGREE) as f32,
+        }
+    }
+
+    /// Returns (x, y) in the raster's coordinate system
+    pub fn forward(&self, lat: f64, long: f64) -> (f64, f64) {
+        let phi = lat.to_radians();
+        let lam = long.to_radians() - self.central_meridian;
+        let el = self.ellipsoid;
+
+        let (x, y) = match self.kind {
+            Kind::Geographic => return (long, lat),
+            Kind::TransverseMercator { k0, m0 } => {
+                let ep2 = el.e2 / (1.0 - el.e2);
+                let (sin, cos) = phi.sin_cos();
+                let n = el.a / (1.0 - el.e2 * sin * sin).sqrt();
+                let t = phi.tan().powi(2);
+                let c = ep2 * cos * cos;
+                let a = lam * cos;
+
+                let x = k0
+                    * n
+                    * (a + (1.0 - t + c) * a.powi(3) / 6.0
+                        + (5.0 - 18.0 * t + t * t + 72.0 * c - 58.0 * ep2) * a.powi(5) / 120.0);
+                let y = k0
+                    * (el.meridian_arc(phi) - m0
+                        + n * phi.tan()
+                            * (a * a / 2.0
+                                + (5.0 - t + 9.0 * c + 4.0 * c * c) * a.powi(4) / 24.0
+                                + (61.0 - 58.0 * t + t * t + 600.0 * c - 330.0 * ep2) * a.powi(6)
+                                    / 720.0));
+                (x, y)
+            }
+            Kind::LambertConformalConic(conic) => {
+                let rho = el.a * conic.c * el.t(phi).powf(conic.n);
+                let theta = conic.n * lam;
+                (rho * theta.sin(), conic.rho0 - rho * theta.cos())
+            }
+            Kind::AlbersEqualArea(conic) => {
+                let rho = el.a * (conic.c - conic.n * el.q(phi)).sqrt() / conic.n;
+                let theta = conic.n * lam;
+                (rho * theta.sin(), conic.rho0 - rho * theta.cos())
+            }
+        };
+
+        // False easting and northing are given in the projection's units, not meters
+        (
+            x / self.unit + self.false_easting,
+            y / self.unit + self.false_northing,
+        )
+    }
+}
+
+fn parameter(projcs: &WktNode, names: &[&str]) -> Option<f64> {
+    projcs.children("PARAMETER").find_map(|p| {
+        let name = p.text(0)?;
+        if names.iter().any(|n| n.eq_ignore_ascii_case(name)) {
+            p.number(1)
+        } else {
+            None
+        }
+    })
+}
+
+fn ellipsoid(geogcs: &WktNode) -> Result<Ellipsoid, PrjParseError> {
+    let spheroid = geogcs
+        .child("DATUM")
+        .and_then(|d| d.child("SPHEROID").or_else(|| d.child("ELLIPSOID")))
+        .ok_or(PrjParseError::MissingNode("SPHEROID"))?;
+
+    let a = spheroid
+        .number(1)
+        .ok_or(PrjParseError::MissingNode("SPHEROID"))?;
+    let inverse_flattening = spheroid
+        .number(2)
+        .ok_or(PrjParseError::MissingNode("SPHEROID"))?;
+
+    // An inverse flattening of 0 denotes a sphere
+    let f = if inverse_flattening == 0.0 {
+        0.0
+    } else {
+        1.0 / inverse_flattening
+    };
+
+    Ok(Ellipsoid {
+        a,
+        e2: 2.0 * f - f * f,
+    })
+}
+
+/// Parses the WKT found in .prj files next to rasters. Geographic systems and the Transverse
+/// Mercator (including UTM), Lambert Conformal Conic and Albers projections are supported
+pub fn parse_prj(prj: &str) -> Result<Projection, PrjParseError> {
+    let root = parse_wkt(prj.trim())?;
+
+    if root.name.eq_ignore_ascii_case("GEOGCS") || root.name.eq_ignore_ascii_case("GEOGCRS") {
+        return Ok(Projection {
+            ellipsoid: ellipsoid(&root)?,
+            ..Default::default()
+        });
+    }
+
+    if !root.name.eq_ignore_ascii_case("PROJCS") {
+        return Err(PrjParseError::UnsupportedProjection(root.name));
+    }
+
+    let geogcs = root
+        .child("GEOGCS")
+        .ok_or(PrjParseError::MissingNode("GEOGCS"))?;
+    let el = ellipsoid(geogcs)?;
+
+    let projection_name = root
+        .child("PROJECTION")
+        .and_then(|p| p.text(0))
+        .ok_or(PrjParseError::MissingNode("PROJECTION"))?
+        .to_lowercase();
+
+    let param = |names: &[&str]| parameter(&root, names);
+    let required = |names: &[&str]| {
+        param(names).ok_or_else(|| PrjParseError::MissingParameter(names[0].to_string()))
+    };
+
+    let lat0 = param(&["latitude_of_origin", "latitude_of_center"])
+        .unwrap_or(0.0)
+        .to_radians();
+    let scale_factor = param(&["scale_factor"]).unwrap_or(1.0);
+
+    let kind = match projection_name.as_str() {
+        "transverse_mercator" => Kind::TransverseMercator {
+            k0: scale_factor,
+            m0: el.meridian_arc(lat0),
+        },
+        "lambert_conformal_conic"
+        | "lambert_conformal_conic_1sp"
+        | "lambert_conformal_conic_2sp" => {
+            let phi1 = param(&["standard_parallel_1"])
+                .map(f64::to_radians)
+                .unwrap_or(lat0);
+            let phi2 = param(&["standard_parallel_2"])
+                .map(f64::to_radians)
+                .unwrap_or(phi1);
+
+            let (m1, m2) = (el.m(phi1), el.m(phi2));
+            let (t1, t2) = (el.t(phi1), el.t(phi2));
+            let n = if (phi1 - phi2).abs() < 1e-10 {
+                phi1.sin()
+            } else {
+                (m1.ln() - m2.ln()) / (t1.ln() - t2.ln())
+            };
+            let f = m1 / (n * t1.powf(n)) * scale_factor;
+
+            Kind::LambertConformalConic(Conic {
+                n,
+                c: f,
+                rho0: el.a * f * el.t(lat0).powf(n),
+            })
+        }
+        "albers" | "albers_conic_equal_area" => {
+            let phi1 = required(&["standard_parallel_1"])?.to_radians();
+            let phi2 = required(&["standard_parallel_2"])?.to_radians();
+
+            let (m1, m2) = (el.m(phi1), el.m(phi2));
+            let (q1, q2) = (el.q(phi1), el.q(phi2));
+            let n = if (phi1 - phi2).abs() < 1e-10 {
+                phi1.sin()
+            } else {
+                (m1 * m1 - m2 * m2) / (q2 - q1)
+            };
+            let c = m1 * m1 + n * q1;
+
+            Kind::AlbersEqualArea(Conic {
+                n,
+                c,
+                rho0: el.a * (c - n * el.q(lat0)).sqrt() / n,
+            })
+        }
+        _ => return Err(PrjParseError::UnsupportedProjection(projection_name)),
+    };
+
+    // The last UNIT directly under PROJCS is the linear unit, the GEOGCS one is angular
+    let unit = root
+        .children("UNIT")
+        .last()
+        .and_then(|u| u.number(1))
+        .unwrap_or(1.0);
+
+    Ok(Projection {
+        kind,
+        ellipsoid: el,
+        central_meridian: param(&["central_meridian", "longitude_of_center"])
+            .unwrap_or(0.0)
+            .to_radians(),
+        false_easting: param(&["false_easting"]).unwrap_or(0.0),
+        false_northing: param(&["false_northing"]).unwrap_or(0.0),
+        unit,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// ((lat, long), (x, y))
+    type Case = ((f64, f64), (f64, f64));
+
+    fn assert_projects(prj: &str, tolerance: f64, cases: &[Case]) {
+        let projection = parse_prj(prj).unwrap();
+        for &((lat, long), (x, y)) in cases {
+            let (actual_x, actual_y) = projection.forward(lat, long);
+            assert!(
+                (actual_x - x).abs() < tolerance && (actual_y - y).abs() < tolerance,
+                "({lat}, {long}) projected to ({actual_x:.3}, {actual_y:.3}), expected ({x}, {y})"
+            );
+        }
+    }
+
+    const NAD83_UTM_10N: &str = r#"PROJCS["NAD_1983_UTM_Zone_10N",GEOGCS["GCS_North_American_1983",DATUM["D_North_American_1983",SPHEROID["GRS_1980",6378137.0,298.257222101]],PRIMEM["Greenwich",0.0],UNIT["Degree",0.0174532925199433]],PROJECTION["Transverse_Mercator"],PARAMETER["False_Easting",500000.0],PARAMETER["False_Northing",0.0],PARAMETER["Central_Meridian",-123.0],PARAMETER["Scale_Factor",0.9996],PARAMETER["Latitude_Of_Origin",0.0],UNIT["Meter",1.0]]"#;
+
+    #[test]
+    fn utm() {
+        // Reference values from the Krüger series, which is accurate to well under a millimeter
+        let cases = [
+            ((49.0, -123.0), (500000.000, 5427455.781)),
+            ((49.25, -123.1), (492722.425, 5455252.511)),
+            ((47.6062, -122.3321), (550200.213, 5272748.591)),
+            ((37.7749, -122.4194), (551130.768, 4180998.881)),
+            ((60.0, -126.0), (332705.179, 6655205.484)),
+        ];
+        assert_projects(NAD83_UTM_10N, 0.03, &cases);
+
+        // The same system as GDAL writes it
+        let ogc = r#"PROJCS["NAD83 / UTM zone 10N",
+            GEOGCS["NAD83",
+                DATUM["North_American_Datum_1983",
+                    SPHEROID["GRS 1980",6378137,298.257222101,AUTHORITY["EPSG","7019"]],
+                    TOWGS84[0,0,0,0,0,0,0],
+                    AUTHORITY["EPSG","6269"]],
+                PRIMEM["Greenwich",0,AUTHORITY["EPSG","8901"]],
+                UNIT["degree",0.0174532925199433,AUTHORITY["EPSG","9122"]],
+                AUTHORITY["EPSG","4269"]],
+            PROJECTION["Transverse_Mercator"],
+            PARAMETER["latitude_of_origin",0],
+            PARAMETER["central_meridian",-123],
+            PARAMETER["scale_factor",0.9996],
+            PARAMETER["false_easting",500000],
+            PARAMETER["false_northing",0],
+            UNIT["metre",1,AUTHORITY["EPSG","9001"]],
+            AXIS["Easting",EAST],
+            AXIS["Northing",NORTH],
+            AUTHORITY["EPSG","26910"]]"#;
+        assert_projects(ogc, 0.03, &cases);
+    }
+
+    #[test]
+    fn utm_from_epsg() {
+        let projection = Projection::from_epsg(26910).unwrap();
+        let (x, y) = projection.forward(49.25, -123.1);
+        assert!((x - 492722.425).abs() < 0.03 && (y - 5455252.511).abs() < 0.03);
+
+        // WGS84 and GRS80 differ by far less than a centimeter here
+        let projection = Projection::from_epsg(32610).unwrap();
+        let (x, y) = projection.forward(49.25, -123.1);
+        assert!((x - 492722.425).abs() < 0.03 && (y - 5455252.511).abs() < 0.03);
+
+        // Southern zones count northing down from 10,000km at the equator
+        let projection = Projection::from_epsg(32756).unwrap();
+        let (x, y) = projection.forward(-33.8688, 151.2093);
+        let (_, y_north) = Projection::from_epsg(32656)
+            .unwrap()
+            .forward(33.8688, 151.2093);
+        assert!((x - 334368.634).abs() < 0.03);
+        assert!((y - (10_000_000.0 - y_north)).abs() < 1e-6);
+
+        assert!(Projection::from_epsg(4326).is_none());
+        assert!(Projection::from_epsg(32767).is_none());
+    }
+
+    #[test]
+    fn transverse_mercator_with_origin() {
+        // British National Grid, example from EPSG guidance note 7-2
+        let prj = r#"PROJCS["OSGB 1936 / British National Grid",GEOGCS["OSGB 1936",DATUM["OSGB_1936",SPHEROID["Airy 1830",6377563.396,299.3249646]],PRIMEM["Greenwich",0],UNIT["degree",0.0174532925199433]],PROJECTION["Transverse_Mercator"],PARAMETER["latitude_of_origin",49],PARAMETER["central_meridian",-2],PARAMETER["scale_factor",0.9996012717],PARAMETER["false_easting",400000],PARAMETER["false_northing",-100000],UNIT["metre",1]]"#;
+        assert_projects(prj, 0.03, &[((50.5, 0.5), (577274.99, 69740.50))]);
+    }
+
+    #[test]
+    fn lambert_conformal_conic() {
+        // Texas South Central in US survey feet, example from EPSG guidance note 7-2
+        let prj = r#"PROJCS["NAD27 / Texas South Central",GEOGCS["NAD27",DATUM["North_American_Datum_1927",SPHEROID["Clarke 1866",6378206.4,294.9786982]],PRIMEM["Greenwich",0],UNIT["degree",0.0174532925199433]],PROJECTION["Lambert_Conformal_Conic_2SP"],PARAMETER["standard_parallel_1",28.3833333333333],PARAMETER["standard_parallel_2",30.2833333333333],PARAMETER["latitude_of_origin",27.8333333333333],PARAMETER["central_meridian",-99],PARAMETER["false_easting",2000000],PARAMETER["false_northing",0],UNIT["US survey foot",0.304800609601219],AXIS["X",EAST],AXIS["Y",NORTH]]"#;
+        assert_projects(prj, 0.03, &[((28.5, -96.0), (2963503.91, 254759.80))]);
+    }
+
+    #[test]
+    fn albers() {
+        // Example from Snyder, Map Projections: A Working Manual, p. 292. Published to the
+        // decimeter
+        let prj = r#"PROJCS["NAD_1927_Albers",GEOGCS["GCS_North_American_1927",DATUM["D_North_American_1927",SPHEROID["Clarke_1866",6378206.4,294.9786982]],PRIMEM["Greenwich",0.0],UNIT["Degree",0.0174532925199433]],PROJECTION["Albers"],PARAMETER["False_Easting",0.0],PARAMETER["False_Northing",0.0],PARAMETER["Central_Meridian",-96.0],PARAMETER["Standard_Parallel_1",29.5],PARAMETER["Standard_Parallel_2",45.5],PARAMETER["Latitude_Of_Origin",23.0],UNIT["Meter",1.0]]"#;
+        assert_projects(prj, 0.1, &[((35.0, -75.0), (1885472.7, 1535925.0))]);
+    }
+
+    #[test]
+    fn geographic() {
+        let prj = r#"GEOGCS["GCS_WGS_1984",DATUM["D_WGS_1984",SPHEROID["WGS_1984",6378137.0,298.257223563]],PRIMEM["Greenwich",0.0],UNIT["Degree",0.0174532925199433]]"#;
+        assert_projects(prj, 1e-12, &[((49.25, -123.1), (-123.1, 49.25))]);
+    }
+
+    #[test]
+    fn unsupported() {
+        let prj = r#"PROJCS["World_Robinson",GEOGCS["GCS_WGS_1984",DATUM["D_WGS_1984",SPHEROID["WGS_1984",6378137.0,298.257223563]],PRIMEM["Greenwich",0.0],UNIT["Degree",0.0174532925199433]],PROJECTION["Robinson"],PARAMETER["Central_Meridian",0.0],UNIT["Meter",1.0]]"#;
+        assert!(matches!(
+            parse_prj(prj),
+            Err(PrjParseError::UnsupportedProjection(p)) if p == "robinson"
+        ));
+        assert!(matches!(
+            parse_prj("PROJCS[\"broken\""),
+            Err(PrjParseError::InvalidWkt(_))
+        ));
+    }
+}
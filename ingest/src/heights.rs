@@ -0,0 +1,400 @@
+use crate::elevation_data::ElevationData;
+use common::{distance_m, Data, Edge, Node, Way};
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+    str::FromStr,
+};
+
+/// Edges longer than this get the DEM sampled between their nodes. Roughly the resolution of
+/// commonly available DEMs, sampling more often than that doesn't reveal anything new
+const SAMPLE_SPACING_M: f32 = 30.0;
+
+/// Grades over shorter runs than this are mostly noise in the heights
+const MIN_GRADE_RUN_M: f32 = 1.0;
+
+/// Bridges, tunnels and anything on a non zero layer doesn't follow the terrain. Sampling the DEM
+/// along them gives the height of the valley a bridge crosses or the mountain a tunnel goes
+/// through
+fn is_structure(data: &Data, way: &Way) -> bool {
+    let flagged = |key| data.tag_value(way, key).is_some_and(|v| v != "no");
+    let layered = data
+        .tag_value(way, "layer")
+        .and_then(|v| v.trim().parse::<i32>().ok())
+        .is_some_and(|layer| layer != 0);
+
+    flagged("bridge") || flagged("tunnel") || layered
+}
+
+/// Distance from `anchor` to every node reachable through nodes that aren't fixed
+fn unfixed_distances(
+    anchor: usize,
+    neighbors: &[Vec<(usize, f32)>],
+    fixed: &[bool],
+) -> HashMap<usize, f32> {
+    let mut ret = HashMap::new();
+    // Non-negative floats order the same way as their bit patterns
+    let mut queue = BinaryHeap::new();
+    queue.push(Reverse((0.0f32.to_bits(), anchor)));
+
+    while let Some(Reverse((dist, node))) = queue.pop() {
+        let dist = f32::from_bits(dist);
+        if ret.contains_key(&node) {
+            continue;
+        }
+        ret.insert(node, dist);
+
+        // Don't walk past other anchors
+        if node != anchor && fixed[node] {
+            continue;
+        }
+
+        for (neighbor, len) in &neighbors[node] {
+            if !ret.contains_key(neighbor) {
+                queue.push(Reverse(((dist + len).to_bits(), *neighbor)));
+            }
+        }
+    }
+
+    ret
+}
+
+/// Sets the height of every node that isn't `fixed` from the fixed nodes its run of unfixed
+/// nodes is attached to. Heights are weighted by the inverse distance to each such anchor along
+/// the graph, which for the common case of a run with two ends is a linear interpolation
+fn interpolate_unfixed(data: &mut Data, neighbors: &[Vec<(usize, f32)>], fixed: &[bool]) {
+    let mut visited = vec![false; data.nodes.len()];
+    for start in 0..data.nodes.len() {
+        if visited[start] || fixed[start] || neighbors[start].is_empty() {
+            continue;
+        }
+
+        // Collect the run of unfixed nodes and the fixed nodes it is attached to
+        let mut component = Vec::new();
+        let mut anchors = Vec::new();
+        let mut stack = vec![start];
+        visited[start] = true;
+        while let Some(node) = stack.pop() {
+            component.push(node);
+            for (neighbor, _) in &neighbors[node] {
+                if fixed[*neighbor] {
+                    if data.nodes[*neighbor].height.is_some() && !anchors.contains(neighbor) {
+                        anchors.push(*neighbor);
+                    }
+                } else if !visited[*neighbor] {
+                    visited[*neighbor] = true;
+                    stack.push(*neighbor);
+                }
+            }
+        }
+
+        // Nothing to anchor to, leave whatever is there
+        if anchors.is_empty() {
+            continue;
+        }
+
+        let mut weighted = vec![(0.0f64, 0.0f64); component.len()];
+        for anchor in anchors {
+            let anchor_height = data.nodes[anchor].height.unwrap_or_default() as f64;
+            let distances = unfixed_distances(anchor, neighbors, fixed);
+            for (node, (sum, total_weight)) in component.iter().zip(&mut weighted) {
+                if let Some(dist) = distances.get(node) {
+                    let weight = 1.0 / (*dist as f64).max(0.01);
+                    *sum += anchor_height * weight;
+                    *total_weight += weight;
+                }
+            }
+        }
+
+        for (node, (sum, total_weight)) in component.into_iter().zip(weighted) {
+            if total_weight > 0.0 {
+                data.nodes[node].height = Some((sum / total_weight) as f32);
+            }
+        }
+    }
+}
+
+fn add_edges(data: &Data, way: &Way, neighbors: &mut [Vec<(usize, f32)>]) {
+    for pair in way.nodes.windows(2) {
+        let len = distance_m(&data.nodes[pair[0]], &data.nodes[pair[1]]);
+        neighbors[pair[0]].push((pair[1], len));
+        neighbors[pair[1]].push((pair[0], len));
+    }
+}
+
+/// Replace the DEM heights of bridge and tunnel nodes with ones interpolated between the points
+/// where the structure meets the ground
+pub fn interpolate_structures(data: &mut Data) {
+    let mut on_ground = vec![false; data.nodes.len()];
+    let mut neighbors = vec![Vec::new(); data.nodes.len()];
+
+    for way in &data.ways {
+        if is_structure(data, way) {
+            add_edges(data, way, &mut neighbors);
+        } else {
+            for node in &way.nodes {
+                on_ground[*node] = true;
+            }
+        }
+    }
+
+    interpolate_unfixed(data, &neighbors, &on_ground);
+}
+
+/// Nodes at the edge of or between rasters, or over water, often end up without a height. Fill
+/// them in from the closest nodes along the network that have one
+pub fn fill_gaps(data: &mut Data) {
+    let has_height = data
+        .nodes
+        .iter()
+        .map(|n| n.height.is_some())
+        .collect::<Vec<_>>();
+
+    let mut neighbors = vec![Vec::new(); data.nodes.len()];
+    for way in &data.ways {
+        add_edges(data, way, &mut neighbors);
+    }
+
+    interpolate_unfixed(data, &neighbors, &has_height);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Smoothing {
+    #[default]
+    None,
+    /// Average over a window of the given length in meters
+    MovingAverage { window: f32 },
+    /// Kalman filter and smoother modelling the road's height as a random walk over distance.
+    /// Both values are standard deviations in meters, the first of the DEM's error, the second
+    /// of how much the road's height changes over 100m
+    Kalman {
+        measurement_noise: f32,
+        process_noise: f32,
+    },
+}
+
+impl FromStr for Smoothing {
+    type Err = String;
+
+    /// none, average[:WINDOW] or kalman[:MEASUREMENT_NOISE[:PROCESS_NOISE]]
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut it = s.split(':');
+        let method = it.next().unwrap_or_default();
+        let mut param = |default: f32| match it.next() {
+            Some(v) => v.parse::<f32>().ok().filter(|v| *v > 0.0),
+            None => Some(default),
+        };
+
+        let ret = match method {
+            "none" => Some(Smoothing::None),
+            "average" => param(50.0).map(|window| Smoothing::MovingAverage { window }),
+            "kalman" => param(3.0)
+                .zip(param(2.0))
+                .map(|(measurement_noise, process_noise)| Smoothing::Kalman {
+                    measurement_noise,
+                    process_noise,
+                }),
+            _ => None,
+        };
+
+        match ret {
+            Some(v) if it.next().is_none() => Ok(v),
+            _ => Err(s.to_string()),
+        }
+    }
+}
+
+fn moving_average(heights: &[f32], positions: &[f32], window: f32) -> Vec<f32> {
+    // Weight each sample by the stretch of road it covers so that densely mapped sections don't
+    // dominate the average
+    let coverage = (0..heights.len())
+        .map(|i| {
+            let prev = positions[i.saturating_sub(1)];
+            let next = positions[(i + 1).min(heights.len() - 1)];
+            ((next - prev) / 2.0).max(0.01)
+        })
+        .collect::<Vec<_>>();
+
+    let mut ret = Vec::with_capacity(heights.len());
+    let mut start = 0;
+    let mut end = 0;
+    for i in 0..heights.len() {
+        while positions[i] - positions[start] > window / 2.0 {
+            start += 1;
+        }
+        while end < heights.len() && positions[end] - positions[i] <= window / 2.0 {
+            end += 1;
+        }
+
+        let mut sum = 0.0;
+        let mut total_weight = 0.0;
+        for j in start..end {
+            sum += heights[j] * coverage[j];
+            total_weight += coverage[j];
+        }
+        ret.push(sum / total_weight);
+    }
+
+    ret
+}
+
+/// Forward Kalman filter followed by a Rauch-Tung-Striebel pass back, so that the result doesn't
+/// lag behind in the direction the way happens to be drawn
+fn kalman(
+    heights: &[f32],
+    positions: &[f32],
+    measurement_noise: f32,
+    process_noise: f32,
+) -> Vec<f32> {
+    let r = measurement_noise * measurement_noise;
+    let q_per_m = process_noise * process_noise / 100.0;
+
+    let mut estimates = Vec::with_capacity(heights.len());
+    let mut variances = Vec::with_capacity(heights.len());
+    let mut predicted_variances = Vec::with_capacity(heights.len());
+
+    estimates.push(heights[0]);
+    variances.push(r);
+    predicted_variances.push(r);
+    for i in 1..heights.len() {
+        let predicted_variance = variances[i - 1] + q_per_m * (positions[i] - positions[i - 1]);
+        let gain = predicted_variance / (predicted_variance + r);
+        estimates.push(estimates[i - 1] + gain * (heights[i] - estimates[i - 1]));
+        variances.push((1.0 - gain) * predicted_variance);
+        predicted_variances.push(predicted_variance);
+    }
+
+    let mut ret = estimates.clone();
+    for i in (0..heights.len().saturating_sub(1)).rev() {
+        let c = variances[i] / predicted_variances[i + 1];
+        ret[i] = estimates[i] + c * (ret[i + 1] - estimates[i]);
+    }
+
+    ret
+}
+
+/// Smooth heights along each way. Nodes shared between ways get the average of what each way
+/// smoothed them to
+pub fn smooth(data: &mut Data, smoothing: Smoothing) {
+    if smoothing == Smoothing::None {
+        return;
+    }
+
+    let mut sums = vec![(0.0f64, 0u32); data.nodes.len()];
+    for way in &data.ways {
+        // Runs of nodes without a height (after gap filling, only those in parts of the network
+        // with no elevation data at all) split the way
+        let runs = way
+            .nodes
+            .split(|n| data.nodes[*n].height.is_none())
+            .filter(|r| !r.is_empty());
+
+        for run in runs {
+            let heights = run
+                .iter()
+                .map(|n| data.nodes[*n].height.unwrap_or_default())
+                .collect::<Vec<_>>();
+
+            let mut positions = Vec::with_capacity(run.len());
+            positions.push(0.0);
+            for pair in run.windows(2) {
+                let last = positions[positions.len() - 1];
+                positions.push(last + distance_m(&data.nodes[pair[0]], &data.nodes[pair[1]]));
+            }
+
+            let smoothed = match smoothing {
+                Smoothing::None => heights,
+                Smoothing::MovingAverage { window } => moving_average(&heights, &positions, window),
+                Smoothing::Kalman {
+                    measurement_noise,
+                    process_noise,
+                } => kalman(&heights, &positions, measurement_noise, process_noise),
+            };
+
+            for (node, height) in run.iter().zip(smoothed) {
+                sums[*node].0 += height as f64;
+                sums[*node].1 += 1;
+            }
+        }
+    }
+
+    for (node, (sum, count)) in data.nodes.iter_mut().zip(sums) {
+        if count > 0 {
+            node.height = Some((sum / count as f64) as f32);
+        }
+    }
+}
+
+/// Heights along the edge as (distance from `from`, height)
+fn edge_profile(
+    from: &Node,
+    to: &Node,
+    elevation_data: &ElevationData,
+    sample_dem: bool,
+) -> Option<Vec<(f32, f32)>> {
+    let (from_height, to_height) = (from.height?, to.height?);
+    let len = distance_m(from, to);
+
+    let mut ret = vec![(0.0, from_height)];
+
+    let to_degrees = |v: i32| v as f32 / 10000000.0;
+    let dem_at = |lat: f32, long: f32| elevation_data.height_at_lat_long(lat, long);
+
+    if sample_dem && len > SAMPLE_SPACING_M {
+        // Node heights may have been filled in or smoothed, so they don't necessarily match the
+        // DEM. Shift the samples so that they line up with the heights at either end
+        let from_offset =
+            dem_at(to_degrees(from.lat), to_degrees(from.long)).map(|h| from_height - h);
+        let to_offset = dem_at(to_degrees(to.lat), to_degrees(to.long)).map(|h| to_height - h);
+        let offsets = match (from_offset, to_offset) {
+            (Some(a), Some(b)) => Some((a, b)),
+            (Some(v), None) | (None, Some(v)) => Some((v, v)),
+            (None, None) => None,
+        };
+
+        if let Some((from_offset, to_offset)) = offsets {
+            let num_samples = (len / SAMPLE_SPACING_M).ceil() as usize;
+            for i in 1..num_samples {
+                let t = i as f32 / num_samples as f32;
+                let lat = to_degrees(from.lat) + (to_degrees(to.lat) - to_degrees(from.lat)) * t;
+                let long =
+                    to_degrees(from.long) + (to_degrees(to.long) - to_degrees(from.long)) * t;
+                if let Some(h) = dem_at(lat, long) {
+                    let offset = from_offset + (to_offset - from_offset) * t;
+                    ret.push((len * t, h + offset));
+                }
+            }
+        }
+    }
+
+    ret.push((len, to_height));
+    Some(ret)
+}
+
+/// Fill in the climbing each edge involves. Long edges are sampled from the DEM so that hills
+/// between their nodes aren't lost. Bridges and tunnels aren't, they run straight between their
+/// ends
+pub fn add_climb(data: &Data, edges: &mut [Edge], elevation_data: &ElevationData) {
+    for edge in edges {
+        let profile = edge_profile(
+            &data.nodes[edge.from],
+            &data.nodes[edge.to],
+            elevation_data,
+            !is_structure(data, &data.ways[edge.way]),
+        );
+
+        (edge.ascent, edge.descent, edge.max_grade) = (0.0, 0.0, 0.0);
+        for step in profile.unwrap_or_default().windows(2) {
+            let ((d1, h1), (d2, h2)) = (step[0], step[1]);
+            let rise = h2 - h1;
+            if rise > 0.0 {
+                edge.ascent += rise;
+            } else {
+                edge.descent -= rise;
+            }
+
+            let grade = rise.abs() / (d2 - d1).max(MIN_GRADE_RUN_M);
+            edge.max_grade = edge.max_grade.max(grade);
+        }
+    }
+}
@@ -0,0 +1,29 @@
+/// Position of a point along a Hilbert curve covering the whole i32 x i32 plane. Points that are
+/// close together in space tend to be close together along the curve, which makes sorting by this
+/// a cheap way to get good memory locality for geometry
+pub fn hilbert_index(x: i32, y: i32) -> u64 {
+    // Shift into u32 space while preserving order
+    let mut x = (x as u32) ^ 0x8000_0000;
+    let mut y = (y as u32) ^ 0x8000_0000;
+
+    // https://en.wikipedia.org/wiki/Hilbert_curve#Applications_and_mapping_algorithms
+    let mut d = 0u64;
+    let mut s = 1u32 << 31;
+    while s > 0 {
+        let rx = (x & s > 0) as u64;
+        let ry = (y & s > 0) as u64;
+        d += (s as u64) * (s as u64) * ((3 * rx) ^ ry);
+
+        if ry == 0 {
+            if rx == 1 {
+                x = u32::MAX - x;
+                y = u32::MAX - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+
+        s >>= 1;
+    }
+
+    d
+}
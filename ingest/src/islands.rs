@@ -0,0 +1,47 @@
+use common::{BoundingBox, Data};
+
+/// Remove ways belonging to connected components with fewer than `min_size` nodes, along with their
+/// nodes. Small disconnected fragments are usually private driveways or ways cut off at the edge of
+/// an extract, and can't be routed to from anywhere else. Must run before edges are built
+pub fn drop_islands(data: &mut Data, min_size: usize) {
+    if min_size == 0 {
+        return;
+    }
+
+    let components = data.components();
+    let mut sizes = vec![0usize; data.nodes.len()];
+    for component in &components {
+        sizes[*component as usize] += 1;
+    }
+
+    let keep: Vec<bool> = components
+        .iter()
+        .map(|c| sizes[*c as usize] >= min_size)
+        .collect();
+
+    // Nodes stay in the same relative order, so the spatial ordering of nodes and ways holds
+    let mut node_mapping = vec![usize::MAX; data.nodes.len()];
+    let mut next_idx = 0;
+    for (i, kept) in keep.iter().enumerate() {
+        if *kept {
+            node_mapping[i] = next_idx;
+            next_idx += 1;
+        }
+    }
+
+    let mut i = 0;
+    data.nodes.retain(|_| {
+        i += 1;
+        keep[i - 1]
+    });
+
+    // Ways never span components, so checking the first node is enough
+    data.ways.retain(|way| keep[way.nodes[0]]);
+    for way in &mut data.ways {
+        for node in &mut way.nodes {
+            *node = node_mapping[*node];
+        }
+    }
+
+    data.metadata.bounding_box = BoundingBox::from_nodes(&data.nodes);
+}
@@ -0,0 +1,286 @@
+//! Builds path-planner [`Data`] from osm extracts and elevation rasters
+
+use common::Data;
+use std::{
+    collections::HashSet,
+    error::Error as StdError,
+    fmt, io,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+pub mod edges;
+pub mod elevation_data;
+pub mod heights;
+mod hilbert;
+pub mod islands;
+mod osm;
+mod osm_xml;
+mod rasters;
+pub mod watch;
+
+pub use elevation_data::{ElevationData, Interpolation};
+pub use heights::Smoothing;
+pub use osm_xml::OsmXmlParseError;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(PathBuf, io::Error),
+    Pbf(osmpbf::Error),
+    OsmXml(PathBuf, OsmXmlParseError),
+    UnknownElevationFormat(PathBuf),
+    NoElevationRasters(PathBuf),
+    Elevation(PathBuf, Box<dyn StdError + Send + Sync>),
+    Projection(PathBuf, elevation_data::projection::PrjParseError),
+    /// [`Builder::two_pass`] was combined with [`Builder::change_path`]
+    TwoPassWithChanges,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use Error::*;
+        match self {
+            Io(p, _) => write!(f, "Failed to read {}", p.display()),
+            Pbf(_) => write!(f, "Failed to read osm pbf"),
+            OsmXml(p, _) => write!(f, "Failed to read osm xml {}", p.display()),
+            UnknownElevationFormat(p) => write!(f, "Unknown elevation format {}", p.display()),
+            NoElevationRasters(p) => write!(f, "No elevation rasters found in {}", p.display()),
+            Elevation(p, _) => write!(f, "Failed to parse elevation data {}", p.display()),
+            Projection(p, _) => write!(f, "Failed to parse projection {}", p.display()),
+            TwoPassWithChanges => write!(f, "osmChange files can't be applied in two pass mode"),
+        }
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        use Error::*;
+        match self {
+            Io(_, e) => Some(e),
+            Pbf(e) => Some(e),
+            OsmXml(_, e) => Some(e),
+            Elevation(_, e) => Some(e.as_ref()),
+            Projection(_, e) => Some(e),
+            UnknownElevationFormat(_) | NoElevationRasters(_) | TwoPassWithChanges => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum OsmInput {
+    Pbf(PathBuf),
+    /// May be .gz or .bz2 compressed
+    Xml(PathBuf),
+}
+
+impl OsmInput {
+    pub fn path(&self) -> &PathBuf {
+        match self {
+            OsmInput::Pbf(p) | OsmInput::Xml(p) => p,
+        }
+    }
+}
+
+/// Steps [`Builder::build_with_progress`] goes through, in order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    ReadingElevation,
+    ReadingOsm,
+    DroppingIslands,
+    EstimatingHeights,
+    BuildingEdges,
+}
+
+impl Stage {
+    pub const COUNT: usize = 5;
+
+    /// Position of this stage in the build, starting at 0
+    pub fn index(self) -> usize {
+        self as usize
+    }
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use Stage::*;
+        let s = match self {
+            ReadingElevation => "Reading elevation data",
+            ReadingOsm => "Reading osm data",
+            DroppingIslands => "Dropping islands",
+            EstimatingHeights => "Estimating heights",
+            BuildingEdges => "Building routing edges",
+        };
+        f.write_str(s)
+    }
+}
+
+/// [`Data`] built from osm input, along with what had to be left out of it
+pub struct OsmData {
+    pub data: Data,
+    /// Osm ids of highways with fewer than 2 nodes we know the location of
+    pub short_ways: Vec<i64>,
+    /// Osm ids of nodes the elevation data had no height for. Their heights are filled in from
+    /// their neighbors later on
+    pub unmeasured_nodes: Vec<i64>,
+}
+
+/// Configures how osm and elevation inputs are turned into [`Data`]. Only the osm input is
+/// required, without elevation data nodes have no heights
+#[derive(Clone)]
+pub struct Builder {
+    osm_input: OsmInput,
+    change_paths: Vec<PathBuf>,
+    elevation_paths: Vec<PathBuf>,
+    interpolation: Interpolation,
+    smoothing: Smoothing,
+    highways: Option<HashSet<String>>,
+    min_island_size: usize,
+    two_pass: bool,
+}
+
+impl Builder {
+    pub fn new(osm_input: OsmInput) -> Builder {
+        Builder {
+            osm_input,
+            change_paths: Vec::new(),
+            elevation_paths: Vec::new(),
+            interpolation: Interpolation::default(),
+            smoothing: Smoothing::default(),
+            highways: None,
+            min_island_size: 0,
+            two_pass: false,
+        }
+    }
+
+    /// osmChange file to apply on top of the osm input. Changes are applied in the order they are
+    /// added. Not supported with [`Builder::two_pass`], building fails with
+    /// [`Error::TwoPassWithChanges`]
+    pub fn change_path(mut self, path: impl Into<PathBuf>) -> Builder {
+        self.change_paths.push(path.into());
+        self
+    }
+
+    /// Elevation raster, or directory of rasters. Where rasters overlap the finest one is used
+    pub fn elevation_path(mut self, path: impl Into<PathBuf>) -> Builder {
+        self.elevation_paths.push(path.into());
+        self
+    }
+
+    pub fn interpolation(mut self, interpolation: Interpolation) -> Builder {
+        self.interpolation = interpolation;
+        self
+    }
+
+    pub fn smoothing(mut self, smoothing: Smoothing) -> Builder {
+        self.smoothing = smoothing;
+        self
+    }
+
+    /// Only keep ways with one of these highway values. All highways are kept by default
+    pub fn highways<I, S>(mut self, highways: I) -> Builder
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.highways = Some(highways.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Drop groups of ways with fewer than this many nodes that connect to nothing else
+    pub fn min_island_size(mut self, min_island_size: usize) -> Builder {
+        self.min_island_size = min_island_size;
+        self
+    }
+
+    /// Read a pbf input twice, only keeping nodes referenced by highways. Slower, but uses far
+    /// less memory on large extracts
+    pub fn two_pass(mut self, two_pass: bool) -> Builder {
+        self.two_pass = two_pass;
+        self
+    }
+
+    /// Every file the output depends on, before elevation directories are expanded
+    pub fn input_paths(&self) -> Vec<PathBuf> {
+        std::iter::once(self.osm_input.path())
+            .chain(&self.elevation_paths)
+            .chain(&self.change_paths)
+            .cloned()
+            .collect()
+    }
+
+    pub fn build(&self) -> Result<OsmData, Error> {
+        self.build_with_progress(|_| ())
+    }
+
+    /// [`Builder::build`], calling `progress` as each [`Stage`] starts
+    pub fn build_with_progress<F>(&self, mut progress: F) -> Result<OsmData, Error>
+    where
+        F: FnMut(Stage),
+    {
+        // The two pass reader never holds the whole extract, so there's nothing to apply changes to
+        if self.two_pass && !self.change_paths.is_empty() {
+            return Err(Error::TwoPassWithChanges);
+        }
+
+        progress(Stage::ReadingElevation);
+        let elevation_files = rasters::elevation_files(&self.elevation_paths)?;
+        let mut elevation_data = rasters::read_elevation_data(&elevation_files)?;
+        elevation_data.set_interpolation(self.interpolation);
+
+        progress(Stage::ReadingOsm);
+        let filter = osm::WayFilter {
+            highways: self.highways.clone(),
+        };
+        let mut osm_data = osm::read_osm_data(
+            &self.osm_input,
+            &self.change_paths,
+            self.two_pass,
+            &elevation_data,
+            &filter,
+        )?;
+
+        let data = &mut osm_data.data;
+        progress(Stage::DroppingIslands);
+        islands::drop_islands(data, self.min_island_size);
+
+        progress(Stage::EstimatingHeights);
+        heights::interpolate_structures(data);
+        heights::fill_gaps(data);
+        heights::smooth(data, self.smoothing);
+
+        progress(Stage::BuildingEdges);
+        let mut edges = edges::build_edges(data);
+        heights::add_climb(data, &mut edges, &elevation_data);
+        data.edges = edges::collapse_chains(data, edges);
+
+        data.metadata.source_files = std::iter::once(self.osm_input.path())
+            .chain(&elevation_files)
+            .chain(&self.change_paths)
+            .map(|p| p.display().to_string())
+            .collect();
+        data.metadata.generated_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        data.metadata.elevation_citation = rasters::read_elevation_citation(&elevation_files);
+
+        Ok(osm_data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn two_pass_rejects_changes() {
+        let builder = Builder::new(OsmInput::Pbf("missing.osm.pbf".into()))
+            .change_path("missing.osc")
+            .two_pass(true);
+        assert!(matches!(builder.build(), Err(Error::TwoPassWithChanges)));
+
+        // Without changes two pass mode gets as far as reading the input
+        let builder = Builder::new(OsmInput::Pbf("missing.osm.pbf".into())).two_pass(true);
+        assert!(matches!(builder.build(), Err(Error::Pbf(_))));
+    }
+}
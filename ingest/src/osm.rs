@@ -0,0 +1,583 @@
+use crate::{
+    elevation_data::ElevationData,
+    hilbert,
+    osm_xml::{self, ChangeAction, OsmXmlElement, OsmXmlParseError},
+    Error, OsmData, OsmInput,
+};
+use common::{BoundingBox, Data, Metadata, Node, StringInterner, Way};
+use osmpbf::Element;
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+};
+
+pub(crate) struct OsmWay {
+    id: i64,
+    node_ids: Vec<i64>,
+    tags: Vec<(String, String)>,
+}
+
+/// Everything we pull out of a pbf in a single pass. Built per element and merged across blobs by
+/// [`osmpbf::ElementReader::par_map_reduce`]
+#[derive(Default)]
+struct PbfContents {
+    nodes: HashMap<i64, Node>,
+    ways: Vec<OsmWay>,
+}
+
+impl PbfContents {
+    fn merge(mut self, mut other: PbfContents) -> PbfContents {
+        self.nodes = merge_node_maps(self.nodes, other.nodes);
+        self.ways.append(&mut other.ways);
+        self
+    }
+}
+
+fn merge_node_maps(mut a: HashMap<i64, Node>, mut b: HashMap<i64, Node>) -> HashMap<i64, Node> {
+    // Always insert into the larger map to avoid re-hashing the bulk of our nodes on every merge
+    if a.len() < b.len() {
+        std::mem::swap(&mut a, &mut b);
+    }
+    a.extend(b);
+    a
+}
+
+fn node_location(elem: &Element) -> Option<(i64, i32, i32)> {
+    match elem {
+        Element::Node(node) => Some((node.id(), node.decimicro_lat(), node.decimicro_lon())),
+        Element::DenseNode(node) => Some((node.id(), node.decimicro_lat(), node.decimicro_lon())),
+        Element::Way(_) | Element::Relation(_) => None,
+    }
+}
+
+fn node_at(id: i64, lat: i32, long: i32, elevation_data: &ElevationData) -> Node {
+    let height =
+        elevation_data.height_at_lat_long(lat as f32 / 10000000.0, long as f32 / 10000000.0);
+    Node {
+        osm_id: id,
+        lat,
+        long,
+        height,
+    }
+}
+
+fn highway_way(elem: &Element) -> Option<OsmWay> {
+    let way = match elem {
+        Element::Way(way) => way,
+        _ => return None,
+    };
+
+    highway(way.id(), way.refs().collect(), way.tags())
+}
+
+/// Builds an [`OsmWay`] if the tags describe a highway. Anything else is not interesting to us
+fn highway<K, V, I>(id: i64, node_ids: Vec<i64>, tags: I) -> Option<OsmWay>
+where
+    K: AsRef<str>,
+    V: AsRef<str>,
+    I: IntoIterator<Item = (K, V)>,
+{
+    let tags: Vec<(K, V)> = tags.into_iter().collect();
+    if !tags.iter().any(|(k, _)| k.as_ref() == "highway") {
+        return None;
+    }
+
+    Some(OsmWay {
+        id,
+        node_ids,
+        tags: tags
+            .iter()
+            .map(|(key, value)| (key.as_ref().to_string(), value.as_ref().to_string()))
+            .collect(),
+    })
+}
+
+/// Node locations and highways of an osm extract, keyed by osm id so that change files can be
+/// applied on top of them. Unlike [`data_from_osm_pbf_two_pass`] this has to hold every node
+/// location, as a change may add a highway that references nodes we would have otherwise
+/// discarded
+#[derive(Default)]
+struct OsmElements {
+    /// (lat, long) in decimicro degrees
+    nodes: HashMap<i64, (i32, i32)>,
+    ways: HashMap<i64, OsmWay>,
+}
+
+impl OsmElements {
+    fn from_osm_pbf<R>(pbf: R) -> Result<OsmElements, osmpbf::Error>
+    where
+        R: std::io::Read + Send,
+    {
+        osmpbf::ElementReader::new(pbf).par_map_reduce(
+            |elem| {
+                let mut ret = OsmElements::default();
+                if let Some((id, lat, long)) = node_location(&elem) {
+                    ret.nodes.insert(id, (lat, long));
+                } else if let Some(way) = highway_way(&elem) {
+                    ret.ways.insert(way.id, way);
+                }
+                ret
+            },
+            OsmElements::default,
+            |mut a, mut b| {
+                if a.nodes.len() < b.nodes.len() {
+                    std::mem::swap(&mut a, &mut b);
+                }
+                a.nodes.extend(b.nodes);
+                a.ways.extend(b.ways);
+                a
+            },
+        )
+    }
+
+    fn from_osm_xml<R: BufRead>(xml: R) -> Result<OsmElements, OsmXmlParseError> {
+        let mut ret = OsmElements::default();
+        osm_xml::for_each_element(xml, |_, elem| ret.apply(ChangeAction::Create, elem))?;
+        Ok(ret)
+    }
+
+    fn apply_change<R: BufRead>(&mut self, osc: R) -> Result<(), OsmXmlParseError> {
+        osm_xml::for_each_element(osc, |action, elem| {
+            // Elements outside of a create/modify/delete block are not valid osmChange, but
+            // treating them as modifications is the most useful interpretation
+            self.apply(action.unwrap_or(ChangeAction::Modify), elem)
+        })
+    }
+
+    fn apply(&mut self, action: ChangeAction, elem: OsmXmlElement) {
+        match (action, elem) {
+            (ChangeAction::Delete, OsmXmlElement::Node { id, .. }) => {
+                self.nodes.remove(&id);
+            }
+            (_, OsmXmlElement::Node { id, location }) => {
+                if let Some(location) = location {
+                    self.nodes.insert(id, location);
+                }
+            }
+            (ChangeAction::Delete, OsmXmlElement::Way { id, .. }) => {
+                self.ways.remove(&id);
+            }
+            (_, OsmXmlElement::Way { id, node_ids, tags }) => {
+                // Modified ways are restated in full, so a way that lost its highway tag needs to
+                // be dropped
+                match highway(id, node_ids, tags) {
+                    Some(way) => self.ways.insert(id, way),
+                    None => self.ways.remove(&id),
+                };
+            }
+        }
+    }
+
+    fn into_data(self, elevation_data: &ElevationData, filter: &WayFilter) -> OsmData {
+        let mut ways: Vec<OsmWay> = self.ways.into_values().collect();
+        ways.retain(|way| filter.keep(way));
+        let relevant_nodes = way_node_ids(&ways);
+
+        let nodes = self
+            .nodes
+            .into_iter()
+            .filter(|(id, _)| relevant_nodes.contains(id))
+            .map(|(id, (lat, long))| (id, node_at(id, lat, long, elevation_data)))
+            .collect();
+
+        remap_osm_ids(nodes, ways, &relevant_nodes)
+    }
+}
+
+/// Which highways to keep
+#[derive(Default, Clone)]
+pub(crate) struct WayFilter {
+    /// Values of the highway tag to keep, or None for all of them
+    pub(crate) highways: Option<HashSet<String>>,
+}
+
+impl WayFilter {
+    fn keep(&self, way: &OsmWay) -> bool {
+        let highways = match &self.highways {
+            Some(v) => v,
+            None => return true,
+        };
+
+        way.tags
+            .iter()
+            .any(|(k, v)| k == "highway" && highways.contains(v))
+    }
+}
+
+/// Read all nodes and highways from the provided pbf in a single pass. Every node in the file is
+/// held in memory until the whole file has been walked. See [`data_from_osm_pbf_two_pass`] for
+/// inputs that are too large for that
+pub(crate) fn data_from_osm_pbf<R>(
+    pbf: R,
+    elevation_data: &ElevationData,
+    filter: &WayFilter,
+) -> Result<OsmData, Error>
+where
+    R: std::io::Read + Send,
+{
+    let pbf_reader = osmpbf::ElementReader::new(pbf);
+
+    let mut contents = pbf_reader
+        .par_map_reduce(
+            |elem| {
+                let mut ret = PbfContents::default();
+                if let Some((id, lat, long)) = node_location(&elem) {
+                    ret.nodes.insert(id, node_at(id, lat, long, elevation_data));
+                } else if let Some(way) = highway_way(&elem) {
+                    ret.ways.push(way);
+                }
+                ret
+            },
+            PbfContents::default,
+            PbfContents::merge,
+        )
+        .map_err(Error::Pbf)?;
+
+    contents.ways.retain(|way| filter.keep(way));
+    let relevant_nodes = way_node_ids(&contents.ways);
+    Ok(remap_osm_ids(
+        contents.nodes,
+        contents.ways,
+        &relevant_nodes,
+    ))
+}
+
+/// Read highways from the pbf at the given path, then walk it a second time to decode only the
+/// nodes those highways reference. This trades a second read of the file for never holding
+/// nodes (or their elevation) that we are going to throw away
+pub(crate) fn data_from_osm_pbf_two_pass(
+    pbf_path: &Path,
+    elevation_data: &ElevationData,
+    filter: &WayFilter,
+) -> Result<OsmData, Error> {
+    let open_pbf = || osmpbf::ElementReader::from_path(pbf_path).map_err(Error::Pbf);
+
+    let mut ways = open_pbf()?
+        .par_map_reduce(
+            |elem| Vec::from_iter(highway_way(&elem)),
+            Vec::new,
+            |mut a, mut b| {
+                a.append(&mut b);
+                a
+            },
+        )
+        .map_err(Error::Pbf)?;
+
+    ways.retain(|way| filter.keep(way));
+    let relevant_nodes = way_node_ids(&ways);
+
+    let nodes = open_pbf()?
+        .par_map_reduce(
+            |elem| {
+                let mut ret = HashMap::new();
+                if let Some((id, lat, long)) = node_location(&elem) {
+                    if relevant_nodes.contains(&id) {
+                        ret.insert(id, node_at(id, lat, long, elevation_data));
+                    }
+                }
+                ret
+            },
+            HashMap::new,
+            merge_node_maps,
+        )
+        .map_err(Error::Pbf)?;
+
+    Ok(remap_osm_ids(nodes, ways, &relevant_nodes))
+}
+
+fn way_node_ids(ways: &[OsmWay]) -> HashSet<i64> {
+    ways.iter()
+        .flat_map(|way| way.node_ids.iter().copied())
+        .collect()
+}
+
+fn remap_osm_ids(
+    nodes: HashMap<i64, Node>,
+    ways: Vec<OsmWay>,
+    relevant_nodes: &HashSet<i64>,
+) -> OsmData {
+    // Once we've walked the whole pbf, we can discard any nodes that are not related to our
+    // paths. Since this will end up being a subset of all ids, we also heal the way references
+    // to be indexes into a linear array of nodes. This has the nice side effect of simplifying
+    // some rendering code. We can just upload this array to the GPU in a vertex buffer and use
+    // the healed node ids as our index buffer
+    //
+    // Nodes are ordered along a Hilbert curve (with the osm id breaking ties) instead of in
+    // whatever order the pbf or our hash maps gave them to us. That makes the output reproducible
+    // between runs, and keeps nearby geometry close together in memory for the renderer and
+    // planner
+    let mut nodes: Vec<(i64, Node)> = nodes
+        .into_iter()
+        .filter(|(k, _)| relevant_nodes.contains(k))
+        .collect();
+    nodes.sort_unstable_by_key(|(id, node)| (hilbert::hilbert_index(node.long, node.lat), *id));
+
+    let (node_mapping, nodes): (HashMap<i64, usize>, Vec<Node>) = nodes
+        .into_iter()
+        .enumerate()
+        .map(|(i, (k, v))| ((k, i), v))
+        .unzip();
+
+    // Extracts cut at a bounding box, or change files deleting nodes, can leave ways
+    // referencing nodes we never saw. Skip those references rather than failing the whole run
+    let mut kept_ways = Vec::new();
+    let mut short_ways = Vec::new();
+    for way in ways.into_iter() {
+        let nodes: Vec<usize> = way
+            .node_ids
+            .iter()
+            .filter_map(|id| node_mapping.get(id).copied())
+            .collect();
+
+        if nodes.len() < 2 {
+            short_ways.push(way.id);
+            continue;
+        }
+
+        kept_ways.push((way, nodes));
+    }
+
+    // Node indices already follow the curve, so ordering ways by their first node keeps them
+    // spatially ordered too. Tags are interned in this final order so string ids don't depend on
+    // the order ways were read in
+    kept_ways.sort_unstable_by_key(|(way, nodes)| (nodes[0], way.id));
+
+    let mut strings = StringInterner::default();
+    let new_ways = kept_ways
+        .into_iter()
+        .map(|(way, nodes)| Way {
+            osm_id: way.id,
+            nodes,
+            tags: way
+                .tags
+                .iter()
+                .map(|(key, value)| strings.tag(key, value))
+                .collect(),
+        })
+        .collect();
+
+    short_ways.sort_unstable();
+
+    let unmeasured_nodes = nodes
+        .iter()
+        .filter(|node| node.height.is_none())
+        .map(|node| node.osm_id)
+        .collect();
+
+    let data = Data {
+        version: common::DATA_VERSION,
+        metadata: Metadata {
+            bounding_box: BoundingBox::from_nodes(&nodes),
+            ..Default::default()
+        },
+        strings: strings.into_strings(),
+        nodes,
+        ways: new_ways,
+        // Filled in once heights are final
+        edges: Vec::new(),
+    };
+
+    OsmData {
+        data,
+        short_ways,
+        unmeasured_nodes,
+    }
+}
+
+/// Read `input` and apply `change_paths` on top of it, in order
+pub(crate) fn read_osm_data(
+    input: &OsmInput,
+    change_paths: &[PathBuf],
+    two_pass: bool,
+    elevation_data: &ElevationData,
+    filter: &WayFilter,
+) -> Result<OsmData, Error> {
+    let open = |path: &Path| osm_xml::open(path).map_err(|e| Error::Io(path.into(), e));
+
+    let mut elements = match input {
+        OsmInput::Pbf(pbf_path) if two_pass => {
+            return data_from_osm_pbf_two_pass(pbf_path, elevation_data, filter)
+        }
+        OsmInput::Pbf(pbf_path) => {
+            let pbf_file = File::open(pbf_path).map_err(|e| Error::Io(pbf_path.clone(), e))?;
+
+            // Without changes to apply we can throw away irrelevant nodes as we go
+            if change_paths.is_empty() {
+                return data_from_osm_pbf(BufReader::new(pbf_file), elevation_data, filter);
+            }
+
+            OsmElements::from_osm_pbf(BufReader::new(pbf_file)).map_err(Error::Pbf)?
+        }
+        OsmInput::Xml(xml_path) => OsmElements::from_osm_xml(open(xml_path)?)
+            .map_err(|e| Error::OsmXml(xml_path.clone(), e))?,
+    };
+
+    for change_path in change_paths {
+        elements
+            .apply_change(open(change_path)?)
+            .map_err(|e| Error::OsmXml(change_path.clone(), e))?;
+    }
+
+    Ok(elements.into_data(elevation_data, filter))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A grid of nodes joined by ways that each carry a few distinct tags
+    fn elements() -> Vec<String> {
+        let mut ret = Vec::new();
+        for i in 0..20 {
+            ret.push(format!(
+                r#"<node id="{}" lat="{}" lon="{}"/>"#,
+                i + 1,
+                49.0 + (i / 5) as f64 * 0.001,
+                -123.0 + (i % 5) as f64 * 0.001,
+            ));
+        }
+        for i in 0..16 {
+            ret.push(format!(
+                r#"<way id="{}"><nd ref="{}"/><nd ref="{}"/><tag k="highway" v="type{}"/><tag k="name" v="Way {}"/><tag k="ref{}" v="{}"/></way>"#,
+                100 + i,
+                i + 1,
+                i + 2,
+                i % 3,
+                i,
+                i % 4,
+                i * 7,
+            ));
+        }
+        ret
+    }
+
+    fn build_from_xml(elements: &[String]) -> Vec<u8> {
+        let xml = format!("<osm>{}</osm>", elements.concat());
+        let osm_data = OsmElements::from_osm_xml(xml.as_bytes())
+            .unwrap()
+            .into_data(&ElevationData::new(Vec::new()), &WayFilter::default());
+        assert_eq!(osm_data.data.ways.len(), 16);
+
+        let mut ret = Vec::new();
+        common::binary::write(&osm_data.data, &mut ret).unwrap();
+        ret
+    }
+
+    #[test]
+    fn output_is_independent_of_input_order() {
+        let mut elements = elements();
+        let expected = build_from_xml(&elements);
+
+        elements.reverse();
+        assert_eq!(build_from_xml(&elements), expected);
+
+        // Interleave nodes and ways
+        elements.sort_by_key(|e| e.len() % 7);
+        assert_eq!(build_from_xml(&elements), expected);
+
+        for _ in 0..5 {
+            elements.rotate_left(3);
+            assert_eq!(build_from_xml(&elements), expected);
+        }
+    }
+
+    const BASE_OSM: &str = r#"<osm version="0.6">
+        <node id="1" lat="49.0" lon="-123.0"/>
+        <node id="2" lat="49.001" lon="-123.0"/>
+        <node id="3" lat="49.002" lon="-123.0"/>
+        <node id="4" lat="49.002" lon="-123.001"/>
+        <node id="5" lat="49.002" lon="-123.002"><tag k="barrier" v="gate"/></node>
+        <node id="6" lat="49.003" lon="-123.002"/>
+        <way id="10"><nd ref="1"/><nd ref="2"/><nd ref="3"/><tag k="highway" v="residential"/></way>
+        <way id="11"><nd ref="3"/><nd ref="4"/><tag k="highway" v="footway"/></way>
+        <way id="12"><nd ref="4"/><nd ref="5"/><tag k="highway" v="service"/></way>
+        <way id="13"><nd ref="5"/><nd ref="6"/><tag k="building" v="yes"/></way>
+    </osm>"#;
+
+    const CHANGE_OSC: &str = r#"<osmChange version="0.6">
+        <create>
+            <node id="7" lat="49.004" lon="-123.002"/>
+            <way id="14"><nd ref="6"/><nd ref="7"/><tag k="highway" v="path"/></way>
+        </create>
+        <modify>
+            <node id="2" lat="49.001" lon="-123.0005"/>
+            <way id="11"><nd ref="3"/><nd ref="4"/><tag k="building" v="yes"/></way>
+            <way id="13"><nd ref="5"/><nd ref="6"/><tag k="highway" v="track"/></way>
+        </modify>
+        <delete>
+            <node id="1"/>
+            <way id="12"/>
+        </delete>
+        <way id="10"><nd ref="1"/><nd ref="2"/><nd ref="3"/><tag k="highway" v="residential"/><tag k="name" v="Main Street"/></way>
+    </osmChange>"#;
+
+    /// Osm id, osm node ids and tags of a way
+    type WaySummary<'a> = (i64, Vec<i64>, Vec<(&'a str, &'a str)>);
+
+    /// Every way, ordered by osm id
+    fn summarize_ways(osm_data: &OsmData) -> Vec<WaySummary<'_>> {
+        let data = &osm_data.data;
+        let mut ret: Vec<_> = data
+            .ways
+            .iter()
+            .map(|way| {
+                let nodes = way.nodes.iter().map(|n| data.nodes[*n].osm_id).collect();
+                (way.osm_id, nodes, data.tags(way).collect())
+            })
+            .collect();
+        ret.sort_by_key(|(id, ..)| *id);
+        ret
+    }
+
+    fn location_of(osm_data: &OsmData, osm_id: i64) -> Option<(i32, i32)> {
+        let mut nodes = osm_data.data.nodes.iter();
+        nodes.find(|n| n.osm_id == osm_id).map(|n| (n.lat, n.long))
+    }
+
+    #[test]
+    fn apply_change() {
+        let elevation_data = ElevationData::new(Vec::new());
+        let filter = WayFilter::default();
+
+        let base = OsmElements::from_osm_xml(BASE_OSM.as_bytes())
+            .unwrap()
+            .into_data(&elevation_data, &filter);
+        assert_eq!(
+            summarize_ways(&base),
+            [
+                (10, vec![1, 2, 3], vec![("highway", "residential")]),
+                (11, vec![3, 4], vec![("highway", "footway")]),
+                (12, vec![4, 5], vec![("highway", "service")]),
+            ]
+        );
+
+        let mut elements = OsmElements::from_osm_xml(BASE_OSM.as_bytes()).unwrap();
+        elements.apply_change(CHANGE_OSC.as_bytes()).unwrap();
+        let changed = elements.into_data(&elevation_data, &filter);
+
+        assert_eq!(
+            summarize_ways(&changed),
+            [
+                // Outside of any action block, so taken as a modification. Its first node was
+                // deleted, so that reference is skipped
+                (
+                    10,
+                    vec![2, 3],
+                    vec![("highway", "residential"), ("name", "Main Street")]
+                ),
+                // 11 lost its highway tag and 12 was deleted. 13 became a highway
+                (13, vec![5, 6], vec![("highway", "track")]),
+                (14, vec![6, 7], vec![("highway", "path")]),
+            ]
+        );
+
+        assert_eq!(location_of(&changed, 1), None);
+        assert_eq!(location_of(&changed, 2), Some((490010000, -1230005000)));
+        assert_eq!(location_of(&changed, 7), Some((490040000, -1230020000)));
+        // Only referenced by ways that are gone
+        assert_eq!(location_of(&changed, 4), None);
+        assert!(changed.short_ways.is_empty());
+    }
+}
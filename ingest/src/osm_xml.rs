@@ -0,0 +1,206 @@
+use quick_xml::events::{BytesStart, Event};
+use std::{
+    error::Error,
+    fmt,
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::Path,
+};
+
+/// Which section of an osmChange (.osc) document an element was found in. Plain .osm documents
+/// have no sections
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeAction {
+    Create,
+    Modify,
+    Delete,
+}
+
+pub enum OsmXmlElement {
+    Node {
+        id: i64,
+        /// (lat, long) in decimicro degrees. Deleted nodes in change files may omit their location
+        location: Option<(i32, i32)>,
+    },
+    Way {
+        id: i64,
+        node_ids: Vec<i64>,
+        tags: Vec<(String, String)>,
+    },
+}
+
+#[derive(Debug)]
+pub enum OsmXmlParseError {
+    Xml(quick_xml::Error),
+    MissingAttribute {
+        element: &'static str,
+        attribute: &'static str,
+    },
+    InvalidInt(std::num::ParseIntError),
+    InvalidFloat(std::num::ParseFloatError),
+}
+
+impl fmt::Display for OsmXmlParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use OsmXmlParseError::*;
+        match self {
+            Xml(_) => write!(f, "Failed to parse xml"),
+            MissingAttribute { element, attribute } => {
+                write!(f, "<{element}> is missing attribute {attribute}")
+            }
+            InvalidInt(_) => write!(f, "Invalid integer attribute"),
+            InvalidFloat(_) => write!(f, "Invalid float attribute"),
+        }
+    }
+}
+
+impl Error for OsmXmlParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        use OsmXmlParseError::*;
+        match self {
+            Xml(s) => Some(s),
+            InvalidInt(s) => Some(s),
+            InvalidFloat(s) => Some(s),
+            MissingAttribute { .. } => None,
+        }
+    }
+}
+
+/// Open an .osm or .osc file, transparently decompressing .gz and .bz2 files
+pub fn open(path: &Path) -> io::Result<Box<dyn BufRead + Send>> {
+    let f = File::open(path)?;
+
+    let ret: Box<dyn BufRead + Send> = match path.extension().and_then(|e| e.to_str()) {
+        Some("gz") => Box::new(BufReader::new(flate2::read::MultiGzDecoder::new(f))),
+        Some("bz2") => Box::new(BufReader::new(bzip2::read::MultiBzDecoder::new(f))),
+        _ => Box::new(BufReader::new(f)),
+    };
+
+    Ok(ret)
+}
+
+fn attribute(elem: &BytesStart, name: &str) -> Result<Option<String>, OsmXmlParseError> {
+    for attr in elem.attributes() {
+        let attr = attr.map_err(|e| OsmXmlParseError::Xml(e.into()))?;
+        if attr.key.as_ref() == name.as_bytes() {
+            let value = attr.unescape_value().map_err(OsmXmlParseError::Xml)?;
+            return Ok(Some(value.into_owned()));
+        }
+    }
+
+    Ok(None)
+}
+
+fn required_attribute(
+    elem: &BytesStart,
+    element: &'static str,
+    name: &'static str,
+) -> Result<String, OsmXmlParseError> {
+    attribute(elem, name)?.ok_or(OsmXmlParseError::MissingAttribute {
+        element,
+        attribute: name,
+    })
+}
+
+fn parse_id(elem: &BytesStart, element: &'static str) -> Result<i64, OsmXmlParseError> {
+    required_attribute(elem, element, "id")?
+        .parse()
+        .map_err(OsmXmlParseError::InvalidInt)
+}
+
+fn parse_decimicro(value: &str) -> Result<i32, OsmXmlParseError> {
+    let value: f64 = value.parse().map_err(OsmXmlParseError::InvalidFloat)?;
+    Ok((value * 10000000.0).round() as i32)
+}
+
+fn parse_node(elem: &BytesStart) -> Result<OsmXmlElement, OsmXmlParseError> {
+    let id = parse_id(elem, "node")?;
+    let lat = attribute(elem, "lat")?;
+    let long = attribute(elem, "lon")?;
+
+    let location = match (lat, long) {
+        (Some(lat), Some(long)) => Some((parse_decimicro(&lat)?, parse_decimicro(&long)?)),
+        _ => None,
+    };
+
+    Ok(OsmXmlElement::Node { id, location })
+}
+
+/// Walk every node and way in an .osm or .osc document. Relations and metadata are skipped
+pub fn for_each_element<R, F>(xml: R, mut f: F) -> Result<(), OsmXmlParseError>
+where
+    R: BufRead,
+    F: FnMut(Option<ChangeAction>, OsmXmlElement),
+{
+    let mut reader = quick_xml::Reader::from_reader(xml);
+    let mut buf = Vec::new();
+
+    let mut action = None;
+    // Nodes can contain tags, we don't care about those. Ways are only emitted once we've seen
+    // their closing tag so that we have all of their node references and tags
+    let mut current_way: Option<OsmXmlElement> = None;
+
+    loop {
+        buf.clear();
+
+        let (elem, is_empty) = match reader
+            .read_event_into(&mut buf)
+            .map_err(OsmXmlParseError::Xml)?
+        {
+            Event::Start(elem) => (elem, false),
+            Event::Empty(elem) => (elem, true),
+            Event::End(elem) => {
+                match elem.name().as_ref() {
+                    b"create" | b"modify" | b"delete" => action = None,
+                    b"way" => {
+                        if let Some(way) = current_way.take() {
+                            f(action, way);
+                        }
+                    }
+                    _ => (),
+                }
+                continue;
+            }
+            Event::Eof => break,
+            _ => continue,
+        };
+
+        match elem.name().as_ref() {
+            b"create" => action = Some(ChangeAction::Create),
+            b"modify" => action = Some(ChangeAction::Modify),
+            b"delete" => action = Some(ChangeAction::Delete),
+            b"node" => f(action, parse_node(&elem)?),
+            b"way" => {
+                let way = OsmXmlElement::Way {
+                    id: parse_id(&elem, "way")?,
+                    node_ids: Vec::new(),
+                    tags: Vec::new(),
+                };
+
+                if is_empty {
+                    f(action, way);
+                } else {
+                    current_way = Some(way);
+                }
+            }
+            b"nd" => {
+                if let Some(OsmXmlElement::Way { node_ids, .. }) = &mut current_way {
+                    let id = required_attribute(&elem, "nd", "ref")?
+                        .parse()
+                        .map_err(OsmXmlParseError::InvalidInt)?;
+                    node_ids.push(id);
+                }
+            }
+            b"tag" => {
+                if let Some(OsmXmlElement::Way { tags, .. }) = &mut current_way {
+                    let key = required_attribute(&elem, "tag", "k")?;
+                    let value = required_attribute(&elem, "tag", "v")?;
+                    tags.push((key, value));
+                }
+            }
+            _ => (),
+        }
+    }
+
+    Ok(())
+}
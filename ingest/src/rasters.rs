@@ -0,0 +1,159 @@
+use crate::{
+    elevation_data::{
+        self, geotiff, hgt,
+        projection::{self, Projection},
+        ElevationData, ElevationFormat, ElevationSource,
+    },
+    Error,
+};
+use memmap2::Mmap;
+use std::{
+    error::Error as StdError,
+    fs::File,
+    io::{self, BufReader, Read},
+    path::{Path, PathBuf},
+};
+
+/// Expands directories given as elevation inputs into the rasters inside them
+pub(crate) fn elevation_files(elevation_paths: &[PathBuf]) -> Result<Vec<PathBuf>, Error> {
+    let mut ret = Vec::new();
+    for path in elevation_paths {
+        if !path.is_dir() {
+            ret.push(path.clone());
+            continue;
+        }
+
+        let read_dir_err = |e| Error::Io(path.clone(), e);
+        let mut rasters = Vec::new();
+        for entry in std::fs::read_dir(path).map_err(read_dir_err)? {
+            let entry_path = entry.map_err(read_dir_err)?.path();
+            if ElevationFormat::from_path(&entry_path).is_some() {
+                rasters.push(entry_path);
+            }
+        }
+
+        if rasters.is_empty() {
+            return Err(Error::NoElevationRasters(path.clone()));
+        }
+
+        // Directory order is arbitrary, keep the output reproducible
+        rasters.sort();
+        ret.extend(rasters);
+    }
+
+    Ok(ret)
+}
+
+fn read_elevation_source(path: &Path) -> Result<Box<dyn ElevationSource>, Error> {
+    let format = ElevationFormat::from_path(path)
+        .ok_or_else(|| Error::UnknownElevationFormat(path.into()))?;
+
+    let elevation_file = File::open(path).map_err(|e| Error::Io(path.into(), e))?;
+    let elevation_file = BufReader::new(elevation_file);
+
+    let parse_err = |e: Box<dyn StdError + Send + Sync>| Error::Elevation(path.into(), e);
+    let projection = read_projection(path)?;
+
+    let mut grid = match format {
+        ElevationFormat::Asc => {
+            // Safety: inputs are expected to be replaced by renaming over them, which leaves
+            // the mapped file intact. Truncating it in place while we parse is not supported
+            let map = unsafe { Mmap::map(elevation_file.get_ref()) }
+                .map_err(|e| Error::Io(path.into(), e))?;
+            elevation_data::parse_elevation_grid_bytes(&map).map_err(|e| parse_err(e.into()))?
+        }
+        ElevationFormat::AscGz => {
+            let mut bytes = Vec::new();
+            flate2::read::MultiGzDecoder::new(elevation_file)
+                .read_to_end(&mut bytes)
+                .map_err(|e| parse_err(e.into()))?;
+            elevation_data::parse_elevation_grid_bytes(&bytes).map_err(|e| parse_err(e.into()))?
+        }
+        ElevationFormat::Hgt => {
+            let tile_name = path
+                .file_stem()
+                .map(|s| s.to_string_lossy())
+                .unwrap_or_default();
+            hgt::parse_hgt(&tile_name, elevation_file).map_err(|e| parse_err(e.into()))?
+        }
+        ElevationFormat::GeoTiff => {
+            geotiff::parse_geotiff(elevation_file, projection).map_err(|e| parse_err(e.into()))?
+        }
+    };
+
+    if let Some(projection) = projection {
+        grid.set_projection(projection);
+    }
+
+    Ok(Box::new(grid))
+}
+
+/// Rasters without a .prj are assumed to be in WGS84, unless they say otherwise themselves
+fn read_projection(elevation_path: &Path) -> Result<Option<Projection>, Error> {
+    let prj_path = prj_path(elevation_path);
+    let prj = match std::fs::read_to_string(&prj_path) {
+        Ok(v) => v,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(Error::Io(prj_path, e)),
+    };
+
+    let projection = projection::parse_prj(&prj).map_err(|e| Error::Projection(prj_path, e))?;
+
+    Ok(Some(projection))
+}
+
+fn prj_path(elevation_path: &Path) -> PathBuf {
+    // foo.asc.gz is described by foo.prj
+    let elevation_path = match elevation_path.extension() {
+        Some(e) if e.eq_ignore_ascii_case("gz") => elevation_path.with_extension(""),
+        _ => elevation_path.to_path_buf(),
+    };
+    elevation_path.with_extension("prj")
+}
+
+fn citation_path(elevation_path: &Path) -> Option<PathBuf> {
+    elevation_path.parent().map(|p| p.join("citation.txt"))
+}
+
+/// Files next to a raster that are read along with it, whether they exist or not
+pub(crate) fn sidecar_files(elevation_path: &Path) -> Vec<PathBuf> {
+    std::iter::once(prj_path(elevation_path))
+        .chain(citation_path(elevation_path))
+        .collect()
+}
+
+pub(crate) fn read_elevation_data(paths: &[PathBuf]) -> Result<ElevationData, Error> {
+    let sources = paths
+        .iter()
+        .map(|p| read_elevation_source(p))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(ElevationData::new(sources))
+}
+
+/// Elevation providers ship their attribution as a citation.txt next to the raster. Tiles from
+/// the same provider usually share one, so each distinct citation is only included once
+pub(crate) fn read_elevation_citation(elevation_paths: &[PathBuf]) -> Option<String> {
+    let mut citations: Vec<String> = Vec::new();
+    for path in elevation_paths {
+        let citation_path = match citation_path(path) {
+            Some(v) => v,
+            None => continue,
+        };
+
+        let citation = match std::fs::read_to_string(citation_path) {
+            Ok(v) => v.trim().to_string(),
+            Err(_) => continue,
+        };
+
+        if !citations.contains(&citation) {
+            citations.push(citation);
+        }
+    }
+
+    if citations.is_empty() {
+        return None;
+    }
+
+    Some(citations.join("\n\n"))
+}
@@ -0,0 +1,242 @@
+use crate::{elevation_data::ElevationFormat, rasters};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver, Sender},
+    time::{Duration, SystemTime},
+};
+
+/// How long the inputs have to stay quiet after a change notification before we consider them
+/// done being written
+const DEBOUNCE_TIME: Duration = Duration::from_secs(1);
+
+#[derive(PartialEq, Eq)]
+struct Fingerprint {
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+fn fingerprint(path: &Path) -> Option<Fingerprint> {
+    let metadata = fs::metadata(path).ok()?;
+    Some(Fingerprint {
+        modified: metadata.modified().ok(),
+        len: metadata.len(),
+    })
+}
+
+/// Every file a build from `paths` reads. Directories are expanded into the rasters inside them,
+/// and rasters bring along the files next to them that describe them
+fn input_files(paths: &[PathBuf]) -> Vec<PathBuf> {
+    let mut ret = Vec::new();
+    for path in paths {
+        // A directory without rasters fails the build, there is nothing else to look at until
+        // that changes
+        let files = rasters::elevation_files(std::slice::from_ref(path))
+            .unwrap_or_else(|_| vec![path.clone()]);
+
+        for file in files {
+            if ElevationFormat::from_path(&file).is_some() {
+                ret.extend(rasters::sidecar_files(&file));
+            }
+            ret.push(file);
+        }
+    }
+
+    // Rasters in the same directory share a citation
+    ret.sort();
+    ret.dedup();
+    ret
+}
+
+fn fingerprints(paths: &[PathBuf]) -> Vec<(PathBuf, Option<Fingerprint>)> {
+    input_files(paths)
+        .into_iter()
+        .map(|p| {
+            let fingerprint = fingerprint(&p);
+            (p, fingerprint)
+        })
+        .collect()
+}
+
+/// Tracks a set of inputs and blocks until they change. Changes are detected by comparing the
+/// modification times and sizes of every file they stand for, either every `interval`, whenever
+/// the filesystem notifies us that one of them was touched, or both
+pub struct InputWatcher {
+    paths: Vec<PathBuf>,
+    interval: Option<Duration>,
+    last_fingerprints: Option<Vec<(PathBuf, Option<Fingerprint>)>>,
+    rx: Receiver<()>,
+    // Keeps the channel open when we aren't watching so that waiting on rx only ever times out
+    _tx: Sender<()>,
+    // Dropping the watcher stops notifications
+    _watcher: Option<RecommendedWatcher>,
+}
+
+impl InputWatcher {
+    pub fn new(
+        paths: Vec<PathBuf>,
+        interval: Option<Duration>,
+        watch: bool,
+    ) -> Result<InputWatcher, notify::Error> {
+        let (tx, rx) = mpsc::channel();
+
+        let watcher = if watch {
+            let tx = tx.clone();
+            // The inputs themselves catch rasters being added to a directory
+            let mut watched = input_files(&paths);
+            watched.extend(paths.iter().cloned());
+            Some(watch_paths(&watched, move || {
+                let _ = tx.send(());
+            })?)
+        } else {
+            None
+        };
+
+        Ok(InputWatcher {
+            paths,
+            interval,
+            last_fingerprints: None,
+            rx,
+            _tx: tx,
+            _watcher: watcher,
+        })
+    }
+
+    /// Blocks until any input differs from what it was the last time this returned. The first
+    /// call returns immediately
+    pub fn wait_for_change(&mut self) {
+        loop {
+            let fingerprints = fingerprints(&self.paths);
+            if self.last_fingerprints.as_ref() != Some(&fingerprints) {
+                self.last_fingerprints = Some(fingerprints);
+                return;
+            }
+
+            let notified = match self.interval {
+                Some(interval) => self.rx.recv_timeout(interval).is_ok(),
+                None => self.rx.recv().is_ok(),
+            };
+
+            // Large inputs take a while to copy into place. Wait for them to settle so we don't
+            // start parsing a half written file
+            if notified {
+                while self.rx.recv_timeout(DEBOUNCE_TIME).is_ok() {}
+            }
+        }
+    }
+}
+
+/// Canonical form of a path that may not exist yet, as long as its directory does
+fn canonicalize(path: &Path) -> io::Result<PathBuf> {
+    match fs::canonicalize(path) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            let (parent, name) = match (path.parent(), path.file_name()) {
+                (Some(parent), Some(name)) => (parent, name),
+                _ => return Err(e),
+            };
+            // The parent of a bare file name is empty
+            let parent = if parent.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                parent
+            };
+            Ok(fs::canonicalize(parent)?.join(name))
+        }
+        res => res,
+    }
+}
+
+/// Calls `on_change` from a background thread whenever the filesystem reports activity on one of
+/// `paths`. Files don't have to exist yet, but their directories do. Notifications stop when the
+/// returned watcher is dropped
+pub fn watch_paths<F>(paths: &[PathBuf], on_change: F) -> Result<RecommendedWatcher, notify::Error>
+where
+    F: Fn() + Send + 'static,
+{
+    // Inputs are often replaced by renaming a new file over the old one, which a watch on the
+    // file itself would not survive. Watch the containing directories instead and filter down
+    // to the paths we care about
+    let paths = paths
+        .iter()
+        .map(|p| canonicalize(p).map_err(notify::Error::io))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let watched_paths = paths.clone();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let event = match event {
+            Ok(v) => v,
+            Err(_) => return,
+        };
+
+        let is_input = |p: &PathBuf| watched_paths.iter().any(|w| p.starts_with(w));
+        if event.paths.iter().any(is_input) {
+            on_change();
+        }
+    })?;
+
+    let mut dirs: Vec<&Path> = paths
+        .iter()
+        .map(|path| match path.parent() {
+            Some(v) if !path.is_dir() => v,
+            _ => path.as_path(),
+        })
+        .collect();
+    dirs.sort();
+    dirs.dedup();
+
+    for dir in dirs {
+        watcher.watch(dir, RecursiveMode::NonRecursive)?;
+    }
+
+    Ok(watcher)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fingerprints_cover_rasters_and_sidecars() {
+        let dir = std::env::temp_dir().join(format!("ingest-watch-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir(&dir).unwrap();
+        let raster = dir.join("a.asc");
+        fs::write(&raster, "1").unwrap();
+
+        let paths = [dir.clone()];
+        let mut last = fingerprints(&paths);
+        let mut assert_changed = |what: &str| {
+            let current = fingerprints(&paths);
+            assert!(current != last, "{what} was not noticed");
+            last = current;
+        };
+
+        fs::write(dir.join("b.asc.gz"), "2").unwrap();
+        assert_changed("adding a raster");
+        fs::write(dir.join("a.prj"), "GEOGCS").unwrap();
+        assert_changed("adding a .prj");
+        fs::write(dir.join("b.prj"), "GEOGCS").unwrap();
+        assert_changed("adding a .prj for a compressed raster");
+        fs::write(dir.join("citation.txt"), "someone").unwrap();
+        assert_changed("adding a citation");
+        fs::write(dir.join("a.prj"), "PROJCS[]").unwrap();
+        assert_changed("changing a .prj");
+        fs::remove_file(&raster).unwrap();
+        assert_changed("removing a raster");
+
+        fs::write(dir.join("notes.txt"), "unrelated").unwrap();
+        assert!(
+            fingerprints(&paths) == last,
+            "unrelated files changed the fingerprint"
+        );
+
+        // Rasters given directly bring their sidecars along too
+        let direct = [dir.join("b.asc.gz")];
+        let before = fingerprints(&direct);
+        fs::write(dir.join("b.prj"), "PROJCS[]").unwrap();
+        assert!(fingerprints(&direct) != before);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,280 @@
+use common::{Data, DataVersion};
+use eframe::egui;
+use ingest::{elevation_data::ElevationFormat, OsmInput};
+use notify::RecommendedWatcher;
+use path_planner::{Color, PreparedData};
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufRead, BufReader, Read},
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How long a source has to stay quiet after a change before it is reloaded, so we don't read
+/// files that are still being written
+const RELOAD_DELAY: Duration = Duration::from_secs(1);
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io(PathBuf, io::Error),
+    Json(serde_json::Error),
+    Binary(common::binary::ReadError),
+    InvalidData(common::ValidationError),
+    Ingest(ingest::Error),
+    /// The loading thread went away without telling us how it went
+    Interrupted,
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use LoadError::*;
+
+        match self {
+            Io(p, e) => write!(f, "Io error on {p}: {e}", p = p.display()),
+            Json(e) => write!(f, "Failed to parse json: {e}"),
+            Binary(e) => write!(f, "Failed to parse binary data: {e}"),
+            InvalidData(e) => write!(f, "Invalid data: {e}"),
+            Ingest(_) => write!(f, "Failed to build data"),
+            Interrupted => write!(f, "Loading was interrupted"),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LoadError::Ingest(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// What a file handed to us is, going by its name
+pub enum InputKind {
+    /// data.json or data.bin generated by the server
+    Data,
+    Osm(OsmInput),
+    Elevation,
+}
+
+impl InputKind {
+    pub fn from_path(path: &Path) -> InputKind {
+        let name = path
+            .file_name()
+            .map(|s| s.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+
+        if name.ends_with(".pbf") {
+            InputKind::Osm(OsmInput::Pbf(path.to_path_buf()))
+        } else if [".osm", ".osm.gz", ".osm.bz2"]
+            .iter()
+            .any(|ext| name.ends_with(ext))
+        {
+            InputKind::Osm(OsmInput::Xml(path.to_path_buf()))
+        } else if path.is_dir() || ElevationFormat::from_path(path).is_some() {
+            InputKind::Elevation
+        } else {
+            InputKind::Data
+        }
+    }
+}
+
+/// Where to load [`Data`] from
+#[derive(Clone)]
+pub enum Source {
+    Data(PathBuf),
+    Osm {
+        input: OsmInput,
+        elevation_paths: Vec<PathBuf>,
+    },
+}
+
+impl Source {
+    pub fn path(&self) -> &Path {
+        match self {
+            Source::Data(p) => p,
+            Source::Osm { input, .. } => input.path(),
+        }
+    }
+
+    /// Everything the loaded data depends on
+    pub fn paths(&self) -> Vec<PathBuf> {
+        match self {
+            Source::Data(p) => vec![p.clone()],
+            Source::Osm {
+                input,
+                elevation_paths,
+            } => std::iter::once(input.path())
+                .chain(elevation_paths)
+                .cloned()
+                .collect(),
+        }
+    }
+}
+
+pub fn load_data(path: &Path) -> Result<Data, LoadError> {
+    let f = File::open(path).map_err(|e| LoadError::Io(path.to_path_buf(), e))?;
+    let mut reader = BufReader::new(f);
+
+    // Sniff the format rather than trusting the extension
+    let header = reader
+        .fill_buf()
+        .map_err(|e| LoadError::Io(path.to_path_buf(), e))?;
+
+    let data: Data = if common::binary::is_binary(header) {
+        common::binary::read(reader).map_err(LoadError::Binary)?
+    } else {
+        let mut json = Vec::new();
+        reader
+            .read_to_end(&mut json)
+            .map_err(|e| LoadError::Io(path.to_path_buf(), e))?;
+
+        let version: DataVersion = serde_json::from_slice(&json).map_err(LoadError::Json)?;
+        version.validate().map_err(LoadError::InvalidData)?;
+        serde_json::from_slice(&json).map_err(LoadError::Json)?
+    };
+
+    data.validate().map_err(LoadError::InvalidData)?;
+
+    Ok(data)
+}
+
+enum Message {
+    Progress(ingest::Stage),
+    Done(Result<PreparedData, LoadError>),
+}
+
+/// Builds [`Data`] and everything the map needs from it on a background thread, so the window
+/// stays responsive while large inputs are read
+pub struct Loader {
+    source: Source,
+    highlights: Vec<(String, Color)>,
+    rx: Receiver<Message>,
+    stage: Option<ingest::Stage>,
+}
+
+impl Loader {
+    /// `ctx` is repainted whenever there is news, so [`Loader::poll`] gets called
+    pub fn spawn(source: Source, highlights: Vec<(String, Color)>, ctx: egui::Context) -> Loader {
+        let (tx, rx) = mpsc::channel();
+
+        let osm_builder = match &source {
+            Source::Data(_) => None,
+            Source::Osm {
+                input,
+                elevation_paths,
+            } => Some(
+                elevation_paths
+                    .iter()
+                    .fold(ingest::Builder::new(input.clone()), |b, p| {
+                        b.elevation_path(p)
+                    }),
+            ),
+        };
+        let data_path = source.path().to_path_buf();
+        let thread_highlights = highlights.clone();
+
+        thread::spawn(move || {
+            let res = match osm_builder {
+                Some(builder) => builder
+                    .build_with_progress(|stage| {
+                        let _ = tx.send(Message::Progress(stage));
+                        ctx.request_repaint();
+                    })
+                    .map(|osm_data| osm_data.data)
+                    .map_err(LoadError::Ingest),
+                None => load_data(&data_path),
+            };
+
+            let res = res.map(|data| {
+                let mut prepared = PreparedData::new(data);
+                // Invalid highlights are left out, same as when they're edited
+                let _ = prepared.set_highlight_list(&thread_highlights);
+                prepared
+            });
+
+            let _ = tx.send(Message::Done(res));
+            ctx.request_repaint();
+        });
+
+        Loader {
+            source,
+            highlights,
+            rx,
+            stage: None,
+        }
+    }
+
+    pub fn source(&self) -> &Source {
+        &self.source
+    }
+
+    /// Highlights the map was colored with
+    pub fn highlights(&self) -> &[(String, Color)] {
+        &self.highlights
+    }
+
+    /// Stage an osm build is at, or None when reading a data file
+    pub fn stage(&self) -> Option<ingest::Stage> {
+        self.stage
+    }
+
+    /// Returns the result once loading has finished
+    pub fn poll(&mut self) -> Option<Result<PreparedData, LoadError>> {
+        loop {
+            match self.rx.try_recv() {
+                Ok(Message::Progress(stage)) => self.stage = Some(stage),
+                Ok(Message::Done(res)) => return Some(res),
+                Err(TryRecvError::Empty) => return None,
+                Err(TryRecvError::Disconnected) => return Some(Err(LoadError::Interrupted)),
+            }
+        }
+    }
+}
+
+/// Notices changes to the files a [`Source`] was loaded from
+pub struct SourceWatcher {
+    rx: Receiver<()>,
+    reload_at: Option<Instant>,
+    // Dropping the watcher stops notifications
+    _watcher: RecommendedWatcher,
+}
+
+impl SourceWatcher {
+    pub fn new(source: &Source, ctx: egui::Context) -> Result<SourceWatcher, notify::Error> {
+        let (tx, rx) = mpsc::channel();
+        let watcher = ingest::watch::watch_paths(&source.paths(), move || {
+            let _ = tx.send(());
+            ctx.request_repaint();
+        })?;
+
+        Ok(SourceWatcher {
+            rx,
+            reload_at: None,
+            _watcher: watcher,
+        })
+    }
+
+    /// True once the source has changed and then settled for [`RELOAD_DELAY`]
+    pub fn poll(&mut self, ctx: &egui::Context) -> bool {
+        let now = Instant::now();
+        while self.rx.try_recv().is_ok() {
+            self.reload_at = Some(now + RELOAD_DELAY);
+        }
+
+        match self.reload_at {
+            Some(t) if t <= now => {
+                self.reload_at = None;
+                true
+            }
+            Some(t) => {
+                ctx.request_repaint_after(t - now);
+                false
+            }
+            None => false,
+        }
+    }
+}
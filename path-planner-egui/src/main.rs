@@ -1,31 +1,39 @@
 use eframe::egui;
 
-use common::Data;
-use egui::{mutex::Mutex, text::LayoutJob, Color32, Style, TextEdit, TextStyle, Visuals};
+use egui::{
+    mutex::Mutex, text::LayoutJob, Align2, Color32, Id, LayerId, Order, ProgressBar, Style,
+    TextEdit, TextStyle, Visuals,
+};
+use loader::{InputKind, Loader, Source, SourceWatcher};
 use path_planner::{Color, PixelCoord, PixelOffset, Size};
 use std::{
     error::Error,
     fmt,
-    fs::File,
-    io::{self, BufReader},
     path::{Path, PathBuf},
     sync::Arc,
 };
 
+mod loader;
+
 enum ArgParseError {
-    MissingArgument,
+    MissingValue(&'static str),
+    UnexpectedArgument(String),
 }
 
 impl fmt::Display for ArgParseError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        writeln!(f, "Missing argument")?;
+        match self {
+            ArgParseError::MissingValue(arg) => writeln!(f, "Missing value for {arg}")?,
+            ArgParseError::UnexpectedArgument(arg) => writeln!(f, "Unexpected argument {arg}")?,
+        }
 
         writeln!(f, "{}", Args::usage())
     }
 }
 
 struct Args {
-    data_path: PathBuf,
+    input_path: Option<PathBuf>,
+    elevation_paths: Vec<PathBuf>,
 }
 
 impl Args {
@@ -35,11 +43,15 @@ impl Args {
             "{exe} \n\
             \n\
             Usage: \n\
-            \t{exe} [OPTIONS] data_path \n\
+            \t{exe} [OPTIONS] [input_path] \n\
             \n\
             Args: \n\
             \t--help|-h: Show this help and exit \n\
-            \tdata_path: Path to data.json generated by server executable",
+            \t--elevation-path|-e <ELEVATION_PATH>: Elevation raster, or directory of rasters, to \
+            read heights from when input_path is osm data. May be given multiple times \n\
+            \tinput_path: Path to data.json or data.bin generated by server executable, or to an \
+            .osm.pbf or .osm file to build the data from directly. Files can also be opened from \
+            the window, or dropped onto it",
             exe = exe.display()
         )
     }
@@ -53,29 +65,36 @@ impl Args {
 
         // Skip process name
         it.next();
-        let arg = match it.next() {
-            Some(v) => v,
-            None => return Err(ArgParseError::MissingArgument),
-        };
 
-        match arg.as_ref() {
-            "--help" | "-h" => {
-                eprintln!("{}", Self::usage());
-                std::process::exit(0);
-            }
-            arg => {
-                let data_path = Path::new(&arg).to_path_buf();
-                Ok(Args { data_path })
+        let mut input_path = None;
+        let mut elevation_paths = Vec::new();
+        while let Some(arg) = it.next() {
+            match arg.as_ref() {
+                "--help" | "-h" => {
+                    eprintln!("{}", Self::usage());
+                    std::process::exit(0);
+                }
+                "--elevation-path" | "-e" => {
+                    let path = it
+                        .next()
+                        .ok_or(ArgParseError::MissingValue("--elevation-path"))?;
+                    elevation_paths.push(Path::new(path.as_ref()).to_path_buf());
+                }
+                arg if input_path.is_none() => input_path = Some(Path::new(arg).to_path_buf()),
+                arg => return Err(ArgParseError::UnexpectedArgument(arg.to_string())),
             }
         }
+
+        Ok(Args {
+            input_path,
+            elevation_paths,
+        })
     }
 }
 
 enum MainError {
     ArgParse(ArgParseError),
     Eframe(eframe::Error),
-    Json(serde_json::Error),
-    Io(PathBuf, io::Error),
 }
 
 impl fmt::Debug for MainError {
@@ -92,8 +111,6 @@ impl fmt::Debug for MainError {
         match self {
             ArgParse(e) => write!(f, "Failed to parse arguments: {e}"),
             Eframe(e) => write!(f, "Eframe error: {e}"),
-            Json(e) => write!(f, "Failed to parse json: {e}"),
-            Io(p, e) => write!(f, "Io error on {p}: {e}", p = p.display()),
         }
     }
 }
@@ -106,28 +123,51 @@ fn main() -> Result<(), MainError> {
         ..Default::default()
     };
 
-    let f = File::open(&args.data_path).map_err(|e| MainError::Io(args.data_path.clone(), e))?;
-
-    let data: Data = serde_json::from_reader(BufReader::new(f)).map_err(MainError::Json)?;
-
     eframe::run_native(
         "Path Planner",
         options,
-        Box::new(move |cc| Box::new(MyApp::new(cc, data))),
+        Box::new(move |cc| {
+            let mut app = MyApp::new(cc, args.elevation_paths);
+            if let Some(path) = args.input_path {
+                app.open(vec![path], &cc.egui_ctx);
+            }
+            Box::new(app)
+        }),
     )
     .map_err(MainError::Eframe)
 }
 
 struct MyApp {
+    gl: Arc<glow::Context>,
     /// Behind an `Arc<Mutex<…>>` so we can pass it to [`egui::PaintCallback`] and paint later.
-    path_planner: Arc<Mutex<path_planner::App>>,
+    /// None until the first load finishes
+    path_planner: Option<Arc<Mutex<path_planner::App>>>,
+    loader: Option<Loader>,
+    load_error: Option<String>,
+    /// Where the data being shown came from
+    source: Option<Source>,
+    /// Reloads the source when it changes on disk
+    watcher: Option<SourceWatcher>,
+    /// Rasters used for the next osm input that is opened
+    elevation_paths: Vec<PathBuf>,
     enable_path_debug: bool,
     next_regex: String,
     highlight_list: Vec<(String, Color)>,
 }
 
+/// Formats an error with everything that caused it
+fn error_chain(e: &dyn Error) -> String {
+    let mut ret = e.to_string();
+    let mut it = e;
+    while let Some(reason) = it.source() {
+        ret += &format!(": {reason}");
+        it = reason;
+    }
+    ret
+}
+
 impl MyApp {
-    fn new(cc: &eframe::CreationContext<'_>, data: Data) -> Self {
+    fn new(cc: &eframe::CreationContext<'_>, elevation_paths: Vec<PathBuf>) -> Self {
         let gl = cc
             .gl
             .as_ref()
@@ -137,43 +177,207 @@ impl MyApp {
             visuals: Visuals::dark(),
             ..Default::default()
         });
-        let planner = match path_planner::App::new(Arc::clone(gl), data) {
+
+        Self {
+            gl: Arc::clone(gl),
+            path_planner: None,
+            loader: None,
+            load_error: None,
+            source: None,
+            watcher: None,
+            elevation_paths,
+            enable_path_debug: false,
+            next_regex: String::new(),
+            highlight_list: Vec::new(),
+        }
+    }
+
+    /// Start loading whatever was picked or dropped. Rasters on their own replace the elevation
+    /// data for the osm input being shown
+    fn open(&mut self, paths: Vec<PathBuf>, ctx: &egui::Context) {
+        let mut data_path = None;
+        let mut osm_input = None;
+        let mut elevation_paths = Vec::new();
+        for path in paths {
+            match InputKind::from_path(&path) {
+                InputKind::Data => data_path = Some(path),
+                InputKind::Osm(input) => osm_input = Some(input),
+                InputKind::Elevation => elevation_paths.push(path),
+            }
+        }
+
+        if !elevation_paths.is_empty() {
+            self.elevation_paths = elevation_paths;
+        }
+
+        let shown_osm_input = match &self.source {
+            Some(Source::Osm { input, .. }) => Some(input.clone()),
+            _ => None,
+        };
+
+        let source = match (data_path, osm_input.or(shown_osm_input)) {
+            (Some(path), _) => Source::Data(path),
+            (None, Some(input)) => Source::Osm {
+                input,
+                elevation_paths: self.elevation_paths.clone(),
+            },
+            (None, None) => return,
+        };
+
+        self.load(source, ctx);
+    }
+
+    fn load(&mut self, source: Source, ctx: &egui::Context) {
+        // Anything still loading is superseded, its result is dropped when it finishes
+        self.loader = Some(Loader::spawn(
+            source,
+            self.highlight_list.clone(),
+            ctx.clone(),
+        ));
+    }
+
+    fn poll_loader(&mut self, ctx: &egui::Context) {
+        let res = match self.loader.as_mut().and_then(Loader::poll) {
+            Some(v) => v,
+            None => return,
+        };
+        let loader = self.loader.take().expect("Loader just finished");
+        let source = loader.source();
+
+        let prepared = match res {
             Ok(v) => v,
             Err(e) => {
-                eprintln!("Failed to create planner: {e}");
-                let mut it = &e as &dyn Error;
-                while let Some(reason) = it.source() {
-                    eprintln!("{reason}");
-                    it = reason;
+                let e = format!(
+                    "Failed to load {}: {}",
+                    source.path().display(),
+                    error_chain(&e)
+                );
+                eprintln!("{e}");
+                self.load_error = Some(e);
+                return;
+            }
+        };
+
+        let reloaded = self.source.as_ref().map(Source::path) == Some(source.path());
+        match &self.path_planner {
+            // Same file with new contents, keep looking at the same place
+            Some(path_planner) if reloaded => path_planner.lock().set_data(prepared),
+            _ => match path_planner::App::from_prepared(Arc::clone(&self.gl), prepared) {
+                Ok(mut planner) => {
+                    planner.set_debug_mode(self.enable_path_debug);
+                    self.path_planner = Some(Arc::new(Mutex::new(planner)));
+                }
+                Err(e) => {
+                    let e = format!("Failed to create planner: {}", error_chain(&e));
+                    eprintln!("{e}");
+                    self.load_error = Some(e);
+                    return;
                 }
-                std::process::exit(1);
+            },
+        }
+
+        // Highlights edited while loading weren't applied yet
+        if loader.highlights() != self.highlight_list.as_slice() {
+            if let Some(path_planner) = &self.path_planner {
+                let _ = path_planner.lock().set_highlight_list(&self.highlight_list);
             }
+        }
+
+        let watched_paths = self.source.as_ref().map(Source::paths);
+        if self.watcher.is_none() || watched_paths != Some(source.paths()) {
+            self.watcher = match SourceWatcher::new(source, ctx.clone()) {
+                Ok(v) => Some(v),
+                Err(e) => {
+                    eprintln!("Failed to watch {}: {e}", source.path().display());
+                    None
+                }
+            };
+        }
+
+        self.load_error = None;
+        self.source = Some(source.clone());
+    }
+
+    fn poll_watcher(&mut self, ctx: &egui::Context) {
+        let changed = match &mut self.watcher {
+            Some(watcher) => watcher.poll(ctx),
+            None => false,
         };
 
-        Self {
-            path_planner: Arc::new(Mutex::new(planner)),
-            enable_path_debug: false,
-            next_regex: String::new(),
-            highlight_list: Vec::new(),
+        if let (true, Some(source)) = (changed, self.source.clone()) {
+            self.load(source, ctx);
         }
     }
-}
 
-impl eframe::App for MyApp {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+    fn top_panel(&mut self, ctx: &egui::Context) {
         egui::TopBottomPanel::top("top panel").show(ctx, |ui| {
             ui.horizontal(|ui| {
+                if ui.button("Open…").clicked() {
+                    let paths = rfd::FileDialog::new()
+                        .add_filter("Map data", &["json", "bin", "pbf", "osm", "gz", "bz2"])
+                        .add_filter("All files", &["*"])
+                        .pick_files();
+                    if let Some(paths) = paths {
+                        self.open(paths, ctx);
+                    }
+                }
+
+                if ui.button("Elevation…").clicked() {
+                    let paths = rfd::FileDialog::new()
+                        .add_filter("Elevation rasters", &["asc", "gz", "hgt", "tif", "tiff"])
+                        .pick_files();
+                    if let Some(paths) = paths {
+                        self.open(paths, ctx);
+                    }
+                }
+
+                if !self.elevation_paths.is_empty() {
+                    let paths: Vec<_> = self
+                        .elevation_paths
+                        .iter()
+                        .map(|p| p.display().to_string())
+                        .collect();
+                    ui.label(format!("{} elevation input(s)", paths.len()))
+                        .on_hover_text(paths.join("\n"));
+                }
+
                 if ui
                     .checkbox(&mut self.enable_path_debug, "Enable path debugging")
                     .changed()
                 {
-                    self.path_planner
-                        .lock()
-                        .set_debug_mode(self.enable_path_debug);
+                    if let Some(path_planner) = &self.path_planner {
+                        path_planner.lock().set_debug_mode(self.enable_path_debug);
+                    }
+                }
+
+                if let Some(loader) = &self.loader {
+                    ui.spinner();
+                    let name = loader.source().path().display();
+                    match loader.stage() {
+                        Some(stage) => {
+                            let progress = stage.index() as f32 / ingest::Stage::COUNT as f32;
+                            ui.add(
+                                ProgressBar::new(progress)
+                                    .desired_width(300.0)
+                                    .text(format!(
+                                        "{name}: {stage} ({}/{})",
+                                        stage.index() + 1,
+                                        ingest::Stage::COUNT
+                                    )),
+                            );
+                        }
+                        None => {
+                            ui.label(format!("Loading {name}"));
+                        }
+                    }
+                } else if let Some(e) = &self.load_error {
+                    ui.colored_label(Color32::LIGHT_RED, e);
                 }
             });
         });
+    }
 
+    fn map_panel(&mut self, ctx: &egui::Context, planner: Arc<Mutex<path_planner::App>>) {
         let response = egui::CentralPanel::default().show(ctx, |ui| {
             let (cursor_delta, cursor_position, cursor_down, scroll_delta) = ui.input(|i| {
                 (
@@ -197,7 +401,7 @@ impl eframe::App for MyApp {
             };
 
             // Clone locals so we can move them into the paint callback:
-            let path_planner = self.path_planner.clone();
+            let path_planner = planner.clone();
 
             if cursor_down {
                 path_planner.lock().move_map(
@@ -231,13 +435,16 @@ impl eframe::App for MyApp {
             };
             ui.painter().add(callback);
 
-            let path_planner = self.path_planner.lock();
+            let path_planner = planner.lock();
 
             let mut info_text = String::new();
 
-            for tag in path_planner.selected_tags() {
-                info_text += tag;
-                info_text += "\n";
+            if let Some(way) = path_planner.selected_way() {
+                info_text += &format!("Way {}\n{}\n", way.osm_id, way.osm_url());
+            }
+
+            for (key, value) in path_planner.selected_tags() {
+                info_text += &format!("{key}={value}\n");
             }
 
             if let Some(cursor_position) = cursor_position.as_ref() {
@@ -329,14 +536,68 @@ impl eframe::App for MyApp {
 
         response.response.context_menu(|ui| {
             if ui.button("Start path").clicked() {
-                self.path_planner.lock().start_path_plan();
+                planner.lock().start_path_plan();
                 ui.close_menu();
             };
 
             if ui.button("Clear path").clicked() {
-                self.path_planner.lock().clear_path_plan();
+                planner.lock().clear_path_plan();
                 ui.close_menu();
             };
         });
     }
+
+    fn drop_overlay(&self, ctx: &egui::Context) {
+        if ctx.input(|i| i.raw.hovered_files.is_empty()) {
+            return;
+        }
+
+        let painter = ctx.layer_painter(LayerId::new(Order::Foreground, Id::new("drop overlay")));
+        let screen_rect = ctx.screen_rect();
+        painter.rect_filled(screen_rect, 0.0, Color32::from_black_alpha(200));
+        painter.text(
+            screen_rect.center(),
+            Align2::CENTER_CENTER,
+            "Drop data, osm or elevation files to open them",
+            TextStyle::Heading.resolve(&ctx.style()),
+            Color32::WHITE,
+        );
+    }
+}
+
+impl eframe::App for MyApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.poll_loader(ctx);
+        self.poll_watcher(ctx);
+
+        let dropped: Vec<PathBuf> = ctx.input(|i| {
+            i.raw
+                .dropped_files
+                .iter()
+                .filter_map(|f| f.path.clone())
+                .collect()
+        });
+        if !dropped.is_empty() {
+            self.open(dropped, ctx);
+        }
+
+        self.top_panel(ctx);
+
+        match self.path_planner.clone() {
+            Some(planner) => self.map_panel(ctx, planner),
+            None => {
+                egui::CentralPanel::default().show(ctx, |ui| {
+                    ui.centered_and_justified(|ui| {
+                        let text = match self.loader {
+                            Some(_) => "Loading…",
+                            None => "Open a data file or an .osm.pbf, or drop one onto the window",
+                        };
+                        ui.label(text);
+                    });
+                });
+            }
+        }
+
+        self.drop_overlay(ctx);
+    }
 }
@@ -1,9 +1,9 @@
-use common::{Data, Node, Way};
+use common::{distance_m, Data, Edge, Node, Way};
 use glow::HasContext;
 use regex::Regex;
 use std::{
     cmp::Reverse,
-    collections::{BinaryHeap, HashMap, HashSet},
+    collections::{BinaryHeap, HashMap},
     error::Error,
     fmt,
     ops::Deref,
@@ -83,7 +83,7 @@ struct ScopedGlEnable<'a> {
 }
 
 impl ScopedGlEnable<'_> {
-    fn new(gl: &glow::Context, flag: u32) -> ScopedGlEnable {
+    fn new(gl: &glow::Context, flag: u32) -> ScopedGlEnable<'_> {
         unsafe {
             let prev_enabled = gl.is_enabled(flag);
             gl.enable(flag);
@@ -130,7 +130,7 @@ pub struct GeoCoord {
     pub lat: f32,
 }
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq)]
 pub struct Color {
     pub r: f32,
     pub g: f32,
@@ -170,6 +170,12 @@ struct VertexData {
     b: f32,
 }
 
+/// Map geometry ready to be uploaded, one line strip per way separated by primitive restarts
+struct MapBuffers {
+    vertices: Vec<VertexData>,
+    indices: Vec<u32>,
+}
+
 #[derive(Debug)]
 pub enum MapRendererCreationError {
     RenderProgramCompilation(ProgramCreationError),
@@ -240,7 +246,10 @@ struct MapRenderer {
 }
 
 impl MapRenderer {
-    fn new(gl: Arc<glow::Context>, data: &Data) -> Result<MapRenderer, MapRendererCreationError> {
+    fn new(
+        gl: Arc<glow::Context>,
+        buffers: &MapBuffers,
+    ) -> Result<MapRenderer, MapRendererCreationError> {
         assert_eq!(std::mem::size_of::<VertexData>(), 24);
 
         use MapRendererCreationError as E;
@@ -268,7 +277,7 @@ impl MapRenderer {
             let index_buffer = ScopedBuffer::new(&gl).map_err(E::MapIndex)?;
             gl.bind_buffer(glow::ELEMENT_ARRAY_BUFFER, Some(*index_buffer));
 
-            let index_buffer_len = construct_bind_map_buffers(&gl, data, &[]);
+            upload_map_buffers(&gl, buffers);
 
             set_vertex_attrib_pointers(&gl, *program);
 
@@ -327,7 +336,7 @@ impl MapRenderer {
                 vertex_array,
                 _vertex_buffer: vertex_buffer,
                 _index_buffer: index_buffer,
-                index_buffer_length: index_buffer_len as i32,
+                index_buffer_length: buffers.indices.len() as i32,
                 wayfinder_program,
                 wayfinder_fbo,
                 _wayfinder_rbo: wayfinder_rbo,
@@ -337,14 +346,21 @@ impl MapRenderer {
         }
     }
 
-    fn set_highlight_list(&self, data: &Data, highlights: &[(Regex, Color)]) {
+    /// Replace the map geometry, the buffers are reused
+    fn set_buffers(&mut self, buffers: &MapBuffers) {
+        self.set_colors(buffers);
+        self.index_buffer_length = buffers.indices.len() as i32;
+    }
+
+    /// Upload buffers built from the same data as the current ones, only differing in color
+    fn set_colors(&self, buffers: &MapBuffers) {
         unsafe {
             self.gl.bind_vertex_array(Some(*self.vertex_array));
             self.gl
                 .bind_buffer(glow::ARRAY_BUFFER, Some(*self._vertex_buffer));
             self.gl
                 .bind_buffer(glow::ELEMENT_ARRAY_BUFFER, Some(*self._index_buffer));
-            construct_bind_map_buffers(&self.gl, data, highlights);
+            upload_map_buffers(&self.gl, buffers);
             self.gl.bind_vertex_array(None);
             self.gl.bind_buffer(glow::ARRAY_BUFFER, None);
             self.gl.bind_buffer(glow::ELEMENT_ARRAY_BUFFER, None);
@@ -548,39 +564,110 @@ impl MapRenderer {
     }
 }
 
+/// The edge's nodes from `from` to `to`, including its shape
+fn edge_nodes(edge: &Edge) -> impl DoubleEndedIterator<Item = usize> + '_ {
+    std::iter::once(edge.from)
+        .chain(edge.shape.iter().copied())
+        .chain(std::iter::once(edge.to))
+}
+
+/// Index of `node` in [`edge_nodes`]. `node` must be on the edge
+fn edge_position(edge: &Edge, node: usize) -> usize {
+    if node == edge.from {
+        0
+    } else if node == edge.to {
+        edge.shape.len() + 1
+    } else {
+        edge.shape
+            .iter()
+            .position(|n| *n == node)
+            .expect("Node not on edge")
+            + 1
+    }
+}
+
 struct PathPlanner {
     data: Arc<Data>,
-    node_neighbors: Vec<Vec<usize>>,
+    /// Indices into [`Data::edges`] touching each junction, i.e. each node that is the end of an
+    /// edge. Nodes in an edge's shape are only ever the start or end of a path, see
+    /// [`PathPlanner::shape_edge`]
+    junction_edges: HashMap<usize, Vec<usize>>,
+    /// Connected component of each node
+    components: Vec<u32>,
 }
 
 impl PathPlanner {
     fn new(data: Arc<Data>) -> PathPlanner {
-        let mut node_neighbors: Vec<HashSet<usize>> = vec![HashSet::new(); data.nodes.len()];
-
-        for way in &data.ways {
-            for (i, node_id) in way.nodes.iter().enumerate() {
-                if i + 1 < way.nodes.len() {
-                    node_neighbors[*node_id].insert(way.nodes[i + 1]);
-                }
+        let mut junction_edges: HashMap<usize, Vec<usize>> = HashMap::new();
 
-                if i > 0 {
-                    node_neighbors[*node_id].insert(way.nodes[i - 1]);
-                }
-            }
+        for (i, edge) in data.edges.iter().enumerate() {
+            junction_edges.entry(edge.from).or_default().push(i);
+            junction_edges.entry(edge.to).or_default().push(i);
         }
 
-        let node_neighbors: Vec<Vec<usize>> = node_neighbors
-            .into_iter()
-            .map(|x| x.into_iter().collect())
-            .collect();
+        let components = data.components();
 
         PathPlanner {
             data,
-            node_neighbors,
+            junction_edges,
+            components,
+        }
+    }
+
+    /// Edge whose shape `node` is part of, None for junctions. Walks every edge, so only meant to
+    /// be called once per search
+    fn shape_edge(&self, node: usize) -> Option<usize> {
+        if self.junction_edges.contains_key(&node) {
+            return None;
+        }
+
+        self.data
+            .edges
+            .iter()
+            .position(|edge| edge.shape.contains(&node))
+    }
+
+    /// Distance between two positions along the edge
+    fn edge_distance(&self, edge: &Edge, a: usize, b: usize) -> f32 {
+        let (a, b) = (a.min(b), a.max(b));
+        if a == 0 && b == edge.shape.len() + 1 {
+            return edge.length;
         }
+
+        edge_nodes(edge)
+            .zip(edge_nodes(edge).skip(1))
+            .skip(a)
+            .take(b - a)
+            .map(|(n1, n2)| distance_m(&self.data.nodes[n1], &self.data.nodes[n2]))
+            .sum()
+    }
+
+    /// Nodes the search should step to from `node` along `edge`, with the distance to them. That's
+    /// the edge's ends, plus `end_node` when it's part of the edge's shape
+    fn edge_steps<'a>(
+        &'a self,
+        edge: &'a Edge,
+        node: usize,
+        end_node: Option<usize>,
+    ) -> impl Iterator<Item = (usize, f32)> + 'a {
+        let position = edge_position(edge, node);
+
+        [Some(edge.from), Some(edge.to), end_node]
+            .into_iter()
+            .flatten()
+            .filter(move |target| *target != node)
+            .map(move |target| {
+                let target_position = edge_position(edge, target);
+                (target, self.edge_distance(edge, position, target_position))
+            })
     }
 
     fn plan_path(&self, start_node: usize, end_node: usize, debug_paths: bool) -> Vec<GeoCoord> {
+        // No path exists, don't bother searching everything reachable from the start to find out
+        if self.components[start_node] != self.components[end_node] {
+            return Vec::new();
+        }
+
         #[derive(PartialEq)]
         struct Item {
             f_score: Reverse<f32>,
@@ -591,13 +678,15 @@ impl PathPlanner {
 
         impl PartialOrd for Item {
             fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-                self.f_score.partial_cmp(&other.f_score)
+                Some(self.cmp(other))
             }
         }
 
         impl Ord for Item {
             fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-                self.partial_cmp(other).expect("Invalid f score")
+                self.f_score
+                    .partial_cmp(&other.f_score)
+                    .expect("Invalid f score")
             }
         }
 
@@ -607,13 +696,18 @@ impl PathPlanner {
             f_score: f32,
         }
 
+        // Paths can start or end partway along an edge
+        let start_edge = self.shape_edge(start_node);
+        let end_edge = self.shape_edge(end_node);
+
         let mut open_set = BinaryHeap::new();
         open_set.push(Item {
             f_score: Reverse(0.0),
             item: start_node,
         });
 
-        let mut came_from: HashMap<usize, usize> = HashMap::new();
+        // Node to the node and edge it was reached from
+        let mut came_from: HashMap<usize, (usize, usize)> = HashMap::new();
         let mut scores = vec![
             Scores {
                 g_score: f32::INFINITY,
@@ -623,7 +717,7 @@ impl PathPlanner {
         ];
         scores[start_node].g_score = 0.0;
         scores[start_node].f_score =
-            distance(&self.data.nodes[start_node], &self.data.nodes[end_node]);
+            heuristic(&self.data.nodes[start_node], &self.data.nodes[end_node]);
 
         const MAX_ITERS: usize = 10000000;
         let mut i = 0;
@@ -644,20 +738,30 @@ impl PathPlanner {
                 }
             }
 
-            for neighbor in &self.node_neighbors[item] {
-                let neighbor_distance =
-                    distance(&self.data.nodes[item], &self.data.nodes[*neighbor]);
-                let tentative_g_score = scores[item].g_score + neighbor_distance;
+            // Only the start node can be expanded without being a junction
+            let edges = match self.junction_edges.get(&item) {
+                Some(v) => v.as_slice(),
+                None => start_edge.as_slice(),
+            };
+            let steps = edges.iter().flat_map(|edge_idx| {
+                let edge = &self.data.edges[*edge_idx];
+                let end_node = (end_edge == Some(*edge_idx)).then_some(end_node);
+                self.edge_steps(edge, item, end_node)
+                    .map(move |(neighbor, length)| (neighbor, length, *edge_idx))
+            });
+
+            for (neighbor, length, edge_idx) in steps {
+                let tentative_g_score = scores[item].g_score + length;
 
-                if tentative_g_score < scores[*neighbor].g_score {
-                    came_from.insert(*neighbor, item);
-                    scores[*neighbor].g_score = tentative_g_score;
-                    scores[*neighbor].f_score = tentative_g_score
-                        + distance(&self.data.nodes[*neighbor], &self.data.nodes[end_node]);
+                if tentative_g_score < scores[neighbor].g_score {
+                    came_from.insert(neighbor, (item, edge_idx));
+                    scores[neighbor].g_score = tentative_g_score;
+                    scores[neighbor].f_score = tentative_g_score
+                        + heuristic(&self.data.nodes[neighbor], &self.data.nodes[end_node]);
 
                     open_set.push(Item {
-                        f_score: Reverse(scores[*neighbor].f_score),
-                        item: *neighbor,
+                        f_score: Reverse(scores[neighbor].f_score),
+                        item: neighbor,
                     });
                 }
             }
@@ -694,6 +798,51 @@ impl fmt::Display for HighlightError {
 
 impl Error for HighlightError {}
 
+fn compile_highlights(
+    highlights: &[(String, Color)],
+) -> Result<Vec<(Regex, Color)>, HighlightError> {
+    highlights
+        .iter()
+        .map(|(s, c)| {
+            let r = Regex::new(s)?;
+            Ok((r, c.clone()))
+        })
+        .collect::<Result<Vec<(Regex, Color)>, regex::Error>>()
+        .map_err(HighlightError)
+}
+
+/// Everything [`App`] derives from [`Data`] that doesn't need a gl context. Building it is the
+/// slow part of loading, so it can be done on another thread and handed to [`App::set_data`]
+pub struct PreparedData {
+    data: Arc<Data>,
+    map_buffers: MapBuffers,
+    path_planner: PathPlanner,
+}
+
+impl PreparedData {
+    pub fn new(data: Data) -> PreparedData {
+        let map_buffers = build_map_buffers(&data, &[]);
+        let data = Arc::new(data);
+        let path_planner = PathPlanner::new(Arc::clone(&data));
+
+        PreparedData {
+            data,
+            map_buffers,
+            path_planner,
+        }
+    }
+
+    /// Same as [`App::set_highlight_list`], for data that isn't shown yet
+    pub fn set_highlight_list(
+        &mut self,
+        highlights: &[(String, Color)],
+    ) -> Result<(), HighlightError> {
+        let highlights = compile_highlights(highlights)?;
+        self.map_buffers = build_map_buffers(&self.data, &highlights);
+        Ok(())
+    }
+}
+
 pub struct App {
     gl: Arc<glow::Context>,
     data: Arc<Data>,
@@ -709,25 +858,25 @@ pub struct App {
 
 impl App {
     pub fn new(gl: Arc<glow::Context>, data: Data) -> Result<App, MapRendererCreationError> {
+        Self::from_prepared(gl, PreparedData::new(data))
+    }
+
+    pub fn from_prepared(
+        gl: Arc<glow::Context>,
+        prepared: PreparedData,
+    ) -> Result<App, MapRendererCreationError> {
         let scale = 10.0;
         let center = GeoCoord {
             long: -123.153946,
             lat: 49.257828,
         };
 
-        let mut tags = HashSet::new();
-        for way in &data.ways {
-            tags.extend(way.tags.iter());
-        }
-
-        let map_renderer = MapRenderer::new(Arc::clone(&gl), &data)?;
-        let data = Arc::new(data);
-        let path_planner = PathPlanner::new(Arc::clone(&data));
+        let map_renderer = MapRenderer::new(Arc::clone(&gl), &prepared.map_buffers)?;
 
         Ok(App {
             gl,
-            data,
-            path_planner,
+            data: prepared.data,
+            path_planner: prepared.path_planner,
             path_start: Default::default(),
             planned_path: Vec::new(),
             map_renderer,
@@ -738,6 +887,26 @@ impl App {
         })
     }
 
+    /// Swap in new data, keeping the view where it is. The path start and the selected position
+    /// are moved to the closest position on the new ways, since way indices don't carry over
+    pub fn set_data(&mut self, prepared: PreparedData) {
+        let path_start = way_position_to_geocoord(&self.data, &self.path_start);
+        let way_position = way_position_to_geocoord(&self.data, &self.way_position);
+
+        self.map_renderer.set_buffers(&prepared.map_buffers);
+        self.data = prepared.data;
+        self.path_planner = prepared.path_planner;
+
+        let snap = |coord: Option<GeoCoord>| match coord {
+            Some(coord) => find_closest_way_position(&self.data, &coord),
+            None => WayPosition::default(),
+        };
+        self.path_start = snap(path_start);
+        self.way_position = snap(way_position);
+
+        self.update_planned_path();
+    }
+
     /// Movement in pixel space, assuming the provided viewport dimensions
     pub fn move_map(&mut self, offset: &PixelOffset, viewport_size: &Size) {
         let center_pixel = PixelCoord {
@@ -805,7 +974,10 @@ impl App {
         let _guards = setup_render(&gl_copy);
 
         self.update_selected_id(cursor_pos, viewport_size);
+        self.update_planned_path();
+    }
 
+    fn update_planned_path(&mut self) {
         if self.path_start.way_id != -1 && self.way_position.way_id != -1 {
             self.planned_path = self.path_planner.plan_path(
                 self.data.ways[self.path_start.way_id as usize].nodes[self.path_start.node_id],
@@ -837,13 +1009,20 @@ impl App {
         }
     }
 
-    pub fn selected_tags(&self) -> Vec<&str> {
+    /// The way under the cursor, if any
+    pub fn selected_way(&self) -> Option<&Way> {
         if self.way_position.way_id >= 0 {
-            self.data.ways[self.way_position.way_id as usize]
-                .tags
-                .iter()
-                .map(|s| s.as_ref())
-                .collect()
+            Some(&self.data.ways[self.way_position.way_id as usize])
+        } else {
+            None
+        }
+    }
+
+    /// (key, value) pairs of the way under the cursor
+    pub fn selected_tags(&self) -> Vec<(&str, &str)> {
+        if self.way_position.way_id >= 0 {
+            let way = &self.data.ways[self.way_position.way_id as usize];
+            self.data.tags(way).collect()
         } else {
             Vec::new()
         }
@@ -858,17 +1037,10 @@ impl App {
     }
 
     pub fn set_highlight_list(&self, highlights: &[(String, Color)]) -> Result<(), HighlightError> {
-        let highlights = highlights
-            .iter()
-            .map(|(s, c)| {
-                let r = Regex::new(s)?;
-                Ok((r, c.clone()))
-            })
-            .collect::<Result<Vec<(Regex, Color)>, regex::Error>>()
-            .map_err(HighlightError)?;
+        let highlights = compile_highlights(highlights)?;
 
         self.map_renderer
-            .set_highlight_list(&self.data, &highlights);
+            .set_colors(&build_map_buffers(&self.data, &highlights));
 
         Ok(())
     }
@@ -929,7 +1101,7 @@ impl App {
 }
 
 #[cfg(not(target_arch = "wasm32"))]
-fn setup_render(gl: &glow::Context) -> [ScopedGlEnable; 4] {
+fn setup_render(gl: &glow::Context) -> [ScopedGlEnable<'_>; 4] {
     [
         ScopedGlEnable::new(gl, glow::PRIMITIVE_RESTART),
         ScopedGlEnable::new(gl, glow::PRIMITIVE_RESTART_FIXED_INDEX),
@@ -939,7 +1111,7 @@ fn setup_render(gl: &glow::Context) -> [ScopedGlEnable; 4] {
 }
 
 #[cfg(target_arch = "wasm32")]
-fn setup_render(gl: &glow::Context) -> [ScopedGlEnable; 0] {
+fn setup_render(gl: &glow::Context) -> [ScopedGlEnable<'_>; 0] {
     []
 }
 
@@ -1083,12 +1255,25 @@ fn find_closest_way_id_to_center(pixels: &[i32]) -> i32 {
 }
 
 fn find_way_position(data: &Data, way_id: i32, coord: &GeoCoord) -> WayPosition {
-    // Step through the given way until we find the location closest to the given coord
-
     if way_id == -1 {
         return WayPosition::default();
     }
 
+    closest_on_way(data, way_id, coord).0
+}
+
+/// Search every way for the position closest to the given coord
+fn find_closest_way_position(data: &Data, coord: &GeoCoord) -> WayPosition {
+    (0..data.ways.len() as i32)
+        .map(|way_id| closest_on_way(data, way_id, coord))
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(position, _)| position)
+        .unwrap_or_default()
+}
+
+/// Position on the way closest to the given coord, along with its squared distance in degrees
+fn closest_on_way(data: &Data, way_id: i32, coord: &GeoCoord) -> (WayPosition, f32) {
+    // Step through the given way until we find the location closest to the given coord
     let way_nodes = &data.ways[way_id as usize].nodes;
 
     let mut min_dist_2 = f32::INFINITY;
@@ -1119,11 +1304,13 @@ fn find_way_position(data: &Data, way_id: i32, coord: &GeoCoord) -> WayPosition
         }
     }
 
-    WayPosition {
+    let position = WayPosition {
         way_id,
         node_id: min_dist_node,
         distance_to_next: min_dist_factor,
-    }
+    };
+
+    (position, min_dist_2)
 }
 
 fn node_to_geocoord(node: &Node) -> GeoCoord {
@@ -1151,36 +1338,51 @@ fn way_position_to_geocoord(data: &Data, position: &WayPosition) -> Option<GeoCo
     Some(GeoCoord { long, lat })
 }
 
-fn distance(n1: &Node, n2: &Node) -> f32 {
-    let long_dist = n2.long - n1.long;
-    let lat_dist = n2.lat - n1.lat;
-
-    let mut long_dist =
-        long_dist as f32 * f32::cos((n2.lat as f32) / 10000000.0 * std::f32::consts::PI / 180.0);
-
-    long_dist /= 10000000.0;
-    let lat_dist = lat_dist as f32 / 10000000.0;
-
-    f32::sqrt(long_dist * long_dist + lat_dist * lat_dist)
+/// Estimated distance left to `end` for the A* search. This must never be more than the length of
+/// the shortest path there, which the great circle distance only satisfies up to f32 rounding of
+/// the edge lengths that make up the path
+fn heuristic(node: &Node, end: &Node) -> f32 {
+    const SAFETY_FACTOR: f32 = 0.999;
+    distance_m(node, end) * SAFETY_FACTOR
 }
 
 fn reconstruct_path(
     data: &Data,
-    came_from: &HashMap<usize, usize>,
+    came_from: &HashMap<usize, (usize, usize)>,
     mut current: usize,
 ) -> Vec<GeoCoord> {
     let mut total_path = vec![node_to_geocoord(&data.nodes[current])];
-    while came_from.contains_key(&current) {
-        current = came_from[&current];
-        total_path.push(node_to_geocoord(&data.nodes[current]))
+    while let Some((previous, edge)) = came_from.get(&current) {
+        // Walk the shape back to the previous node, the current one is already in the path
+        let edge = &data.edges[*edge];
+        let (from, to) = (edge_position(edge, current), edge_position(edge, *previous));
+        let shape: Box<dyn Iterator<Item = usize>> = if from < to {
+            Box::new(edge_nodes(edge).skip(from + 1).take(to - from))
+        } else {
+            let len = edge.shape.len() + 2;
+            Box::new(edge_nodes(edge).rev().skip(len - from).take(from - to))
+        };
+        total_path.extend(shape.map(|n| node_to_geocoord(&data.nodes[n])));
+
+        current = *previous;
     }
 
     total_path
 }
 
-fn way_color(way: &Way, highlights: &[(Regex, Color)]) -> Color {
+fn way_color(data: &Data, way: &Way, highlights: &[(Regex, Color)]) -> Color {
+    if highlights.is_empty() {
+        return Color::from_rgb(0.0, 0.0, 0.0);
+    }
+
+    // Highlights match against a key=value rendering of each tag
+    let tags: Vec<String> = data
+        .tags(way)
+        .map(|(key, value)| format!("{key}={value}"))
+        .collect();
+
     for (r, c) in highlights {
-        for tag in &way.tags {
+        for tag in &tags {
             if r.is_match(tag) {
                 return c.clone();
             }
@@ -1190,11 +1392,7 @@ fn way_color(way: &Way, highlights: &[(Regex, Color)]) -> Color {
     Color::from_rgb(0.0, 0.0, 0.0)
 }
 
-fn construct_bind_map_buffers(
-    gl: &glow::Context,
-    data: &Data,
-    highlights: &[(Regex, Color)],
-) -> usize {
+fn build_map_buffers(data: &Data, highlights: &[(Regex, Color)]) -> MapBuffers {
     let mut min_height = f32::MAX;
     let mut max_height = f32::MIN;
 
@@ -1231,7 +1429,7 @@ fn construct_bind_map_buffers(
     let mut vertex_buffer_data = Vec::new();
     let mut index_buffer_data: Vec<u32> = Vec::new();
     for (i, way) in data.ways.iter().enumerate() {
-        let color = way_color(way, highlights);
+        let color = way_color(data, way, highlights);
 
         for node_id in &way.nodes {
             let node = &data.nodes[*node_id];
@@ -1247,19 +1445,27 @@ fn construct_bind_map_buffers(
             index_buffer_data.push((vertex_buffer_data.len() - 1) as u32);
         }
 
-        index_buffer_data.push(u32::max_value());
+        index_buffer_data.push(u32::MAX);
+    }
+
+    MapBuffers {
+        vertices: vertex_buffer_data,
+        indices: index_buffer_data,
     }
+}
 
+/// Fill the currently bound array and element array buffers
+fn upload_map_buffers(gl: &glow::Context, buffers: &MapBuffers) {
     unsafe {
         let vertex_buffer_u8 = std::slice::from_raw_parts(
-            vertex_buffer_data.as_ptr() as *const u8,
-            vertex_buffer_data.len() * std::mem::size_of::<VertexData>(),
+            buffers.vertices.as_ptr() as *const u8,
+            buffers.vertices.len() * std::mem::size_of::<VertexData>(),
         );
         gl.buffer_data_u8_slice(glow::ARRAY_BUFFER, vertex_buffer_u8, glow::STATIC_DRAW);
 
         let index_buffer_u8 = std::slice::from_raw_parts(
-            index_buffer_data.as_ptr() as *const u8,
-            index_buffer_data.len() * std::mem::size_of::<u32>(),
+            buffers.indices.as_ptr() as *const u8,
+            buffers.indices.len() * std::mem::size_of::<u32>(),
         );
         gl.buffer_data_u8_slice(
             glow::ELEMENT_ARRAY_BUFFER,
@@ -1267,6 +1473,122 @@ fn construct_bind_map_buffers(
             glow::STATIC_DRAW,
         );
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::Metadata;
+
+    fn node(lat: f64, long: f64) -> Node {
+        Node {
+            osm_id: 0,
+            lat: (lat * 10000000.0) as i32,
+            long: (long * 10000000.0) as i32,
+            height: None,
+        }
+    }
+
+    fn way(nodes: &[usize]) -> Way {
+        Way {
+            osm_id: 0,
+            nodes: nodes.to_vec(),
+            tags: Vec::new(),
+        }
+    }
 
-    index_buffer_data.len()
+    /// A street with a side street up to a roundabout, a footpath from the end of the street to the
+    /// roundabout and a loop that only connects to the street at one node
+    fn network() -> Data {
+        let mut nodes: Vec<Node> = (0..6)
+            .map(|i| node(49.0, -123.0 + 0.001 * i as f64))
+            .collect();
+        nodes.extend([
+            node(49.001, -122.997),
+            node(49.002, -122.997),
+            node(49.003, -122.997),
+            node(49.0035, -122.9975),
+            node(49.004, -122.997),
+            node(49.0033, -122.9966),
+            node(49.001, -122.995),
+            node(49.002, -122.995),
+            node(48.999, -122.9985),
+            node(48.9992, -122.9976),
+        ]);
+
+        Data {
+            version: common::DATA_VERSION,
+            metadata: Metadata::default(),
+            strings: Vec::new(),
+            nodes,
+            ways: vec![
+                way(&[0, 1, 2, 3, 4, 5]),
+                way(&[3, 6, 7, 8]),
+                way(&[8, 9, 10, 11, 8]),
+                way(&[5, 12, 13, 11]),
+                way(&[2, 14, 15, 2]),
+            ],
+            edges: Vec::new(),
+        }
+    }
+
+    /// Nodes a path passes through
+    fn path_nodes(data: &Data, path: &[GeoCoord]) -> Vec<usize> {
+        path.iter()
+            .map(|c| {
+                data.nodes
+                    .iter()
+                    .position(|n| {
+                        let n = node_to_geocoord(n);
+                        (n.lat, n.long) == (c.lat, c.long)
+                    })
+                    .unwrap()
+            })
+            .collect()
+    }
+
+    fn path_length(data: &Data, path: &[usize]) -> f32 {
+        path.windows(2)
+            .map(|w| distance_m(&data.nodes[w[0]], &data.nodes[w[1]]))
+            .sum()
+    }
+
+    #[test]
+    fn collapsed_routes_match() {
+        let mut data = network();
+        data.edges = ingest::edges::build_edges(&data);
+        let full = PathPlanner::new(Arc::new(data));
+
+        let mut data = network();
+        let edges = ingest::edges::build_edges(&data);
+        data.edges = ingest::edges::collapse_chains(&data, edges);
+        assert!(data.edges.len() < full.data.edges.len());
+        assert!(data.edges.iter().all(|edge| edge.from != edge.to));
+        let collapsed = PathPlanner::new(Arc::new(data));
+
+        let num_nodes = full.data.nodes.len();
+        for start in 0..num_nodes {
+            for end in 0..num_nodes {
+                let expected = path_nodes(&full.data, &full.plan_path(start, end, false));
+                let actual = path_nodes(&collapsed.data, &collapsed.plan_path(start, end, false));
+                assert_eq!(actual.first(), Some(&end));
+                assert_eq!(actual.last(), Some(&start));
+
+                let (actual, expected) = (
+                    path_length(&collapsed.data, &actual),
+                    path_length(&full.data, &expected),
+                );
+                assert!(
+                    (actual - expected).abs() < 0.01,
+                    "{start} -> {end} is {actual} long collapsed, {expected} uncollapsed"
+                );
+
+                let estimate = heuristic(&full.data.nodes[start], &full.data.nodes[end]);
+                assert!(
+                    estimate <= expected,
+                    "{start} -> {end} estimated at {estimate}, but the path is {expected} long"
+                );
+            }
+        }
+    }
 }